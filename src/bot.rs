@@ -4,35 +4,449 @@
  * @LastEditTime: 2025-12-09 15:20:34
  * @Description:
  */
-use crate::cloudreve::CloudreveClient;
+use crate::cloudreve::{
+    uri, CloudreveClient, FileEntry, FileFilter, FileSource, OrderBy, OrderDirection, SourceOptions,
+};
+use crate::i18n::{tr, Lang, Msg};
+use crate::resolver::ResolverRegistry;
+use crate::state::{
+    ChatSettings, DownloadSource, FolderStats, ListOptions, Pending, PendingAction, PendingInput,
+    StateStore, TelegramFile,
+};
 use anyhow::{anyhow, Result};
-use log::info;
+use futures::future;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use log::{error, info, warn};
+use regex::Regex;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::env;
-use std::sync::Arc;
-use std::time::Duration;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use teloxide::net::Download;
 use teloxide::prelude::*;
 use teloxide::types::{
-    InlineKeyboardButton, InlineKeyboardMarkup, MaybeInaccessibleMessage, MessageId,
-    ReplyParameters,
+    BotCommand, BotCommandScope, Document, ForceReply, InlineKeyboardButton, InlineKeyboardMarkup,
+    InlineQueryResult, InlineQueryResultArticle, InputFile, InputMedia, InputMediaPhoto,
+    InputMessageContent, InputMessageContentText, MaybeInaccessibleMessage, MessageEntityKind,
+    MessageId, MessageOrigin, Recipient, ReplyParameters, User, UserId,
 };
 use teloxide::utils::command::BotCommands;
-use tokio::sync::Mutex;
-use tokio::time::sleep;
+use teloxide::utils::html;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::time::{sleep, timeout};
 
 // Simple in-memory cache for page tokens: Map<"path:page", next_token>
 // Note: This is a simple implementation. In production, use a TTL cache (e.g., moka) to avoid memory leaks.
 type PageTokenCache = Arc<Mutex<HashMap<String, String>>>;
 
+/// How the inline file listing behaves when a folder button is pressed.
+#[derive(Clone, Copy)]
+pub enum BrowseMode {
+    /// Normal browsing: folders open, files fetch a direct link.
+    Browse,
+    /// Choosing the destination folder of the pending download with this id.
+    Pick(u64),
+    /// The recycle bin: entries open a card with restore and purge actions.
+    Trash,
+    /// Picking files of one folder; the selection is kept per message in the state store.
+    Select,
+}
+
+// 按钮的 callback data 为 <动作>:<参数>，动作是两个字母的代码
+macro_rules! actions {
+    ($($name:ident = $code:literal,)*) => {
+        /// What an inline button does, encoded as the prefix of its callback data.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        enum Action {
+            $($name,)*
+        }
+
+        impl Action {
+            fn parse(code: &str) -> Option<Self> {
+                match code {
+                    $($code => Some(Action::$name),)*
+                    _ => None,
+                }
+            }
+
+            fn as_str(self) -> &'static str {
+                match self {
+                    $(Action::$name => $code,)*
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    Open = "cd",
+    Page = "pg",
+    Preview = "pv",
+    SortBy = "so",
+    SortDirection = "sd",
+    Filter = "sf",
+    GetLink = "gl",
+    RefreshLink = "gr",
+    Details = "fi",
+    Rename = "rn",
+    Move = "mv",
+    Delete = "dl",
+    ConfirmDelete = "dc",
+    CancelDelete = "dx",
+    Share = "sh",
+    SendToChat = "sc",
+    Extract = "ex",
+    ExportLinks = "xl",
+    ExportText = "xt",
+    ExportAria2 = "xa",
+    Stats = "fs",
+    Recount = "fr",
+    Select = "se",
+    SelectToggle = "st",
+    SelectPage = "sg",
+    SelectDone = "sq",
+    SelectZip = "sz",
+    SelectDelete = "bd",
+    SelectConfirmDelete = "bc",
+    SelectCancelDelete = "bx",
+    SelectMove = "bm",
+    SelectCopy = "bp",
+    SelectShare = "bs",
+    SelectLinks = "bl",
+    TrashPage = "tg",
+    TrashItem = "ti",
+    Restore = "tr",
+    Purge = "tp",
+    ConfirmPurge = "tq",
+    CancelPurge = "tk",
+    EmptyTrash = "te",
+    ConfirmEmptyTrash = "tc",
+    SubmitDownload = "rd",
+    PickOpen = "pk",
+    PickFolder = "pc",
+    PickPage = "pp",
+    PickSave = "ps",
+    PickRecent = "pr",
+    PickCancel = "px",
+    Settings = "cs",
+    Language = "lg",
+    Noop = "no",
+}
+
+impl Action {
+    /// Callback data of a button with this action.
+    fn data(self, payload: impl std::fmt::Display) -> String {
+        format!("{}:{}", self.as_str(), payload)
+    }
+
+    /// Whether the payload ends with a `StateStore::path_id`, after any other arguments.
+    fn needs_path(self) -> bool {
+        match self {
+            Action::Open
+            | Action::Page
+            | Action::Preview
+            | Action::SortBy
+            | Action::SortDirection
+            | Action::Filter
+            | Action::GetLink
+            | Action::RefreshLink
+            | Action::Details
+            | Action::Rename
+            | Action::Move
+            | Action::Delete
+            | Action::ConfirmDelete
+            | Action::CancelDelete
+            | Action::Share
+            | Action::SendToChat
+            | Action::Extract
+            | Action::ExportLinks
+            | Action::ExportText
+            | Action::ExportAria2
+            | Action::Stats
+            | Action::Recount
+            | Action::Select
+            | Action::TrashItem
+            | Action::Restore
+            | Action::Purge
+            | Action::ConfirmPurge
+            | Action::CancelPurge
+            | Action::PickFolder
+            | Action::PickRecent => true,
+            Action::SelectToggle
+            | Action::SelectPage
+            | Action::SelectDone
+            | Action::SelectZip
+            | Action::SelectDelete
+            | Action::SelectConfirmDelete
+            | Action::SelectCancelDelete
+            | Action::SelectMove
+            | Action::SelectCopy
+            | Action::SelectShare
+            | Action::SelectLinks
+            | Action::TrashPage
+            | Action::EmptyTrash
+            | Action::ConfirmEmptyTrash
+            | Action::SubmitDownload
+            | Action::PickOpen
+            | Action::PickPage
+            | Action::PickSave
+            | Action::PickCancel
+            | Action::Settings
+            | Action::Language
+            | Action::Noop => false,
+        }
+    }
+
+    /// Actions limited to `ADMIN_USERS`: the recycle bin and anything that changes or shares files.
+    fn admin_only(self) -> bool {
+        match self {
+            Action::TrashPage
+            | Action::Rename
+            | Action::Move
+            | Action::Delete
+            | Action::ConfirmDelete
+            | Action::Share
+            | Action::Extract
+            | Action::SelectZip
+            | Action::SelectDelete
+            | Action::SelectConfirmDelete
+            | Action::SelectMove
+            | Action::SelectCopy
+            | Action::SelectShare
+            | Action::TrashItem
+            | Action::Restore
+            | Action::Purge
+            | Action::ConfirmPurge
+            | Action::CancelPurge
+            | Action::EmptyTrash
+            | Action::ConfirmEmptyTrash => true,
+            Action::Open
+            | Action::Page
+            | Action::Preview
+            | Action::SortBy
+            | Action::SortDirection
+            | Action::Filter
+            | Action::GetLink
+            | Action::RefreshLink
+            | Action::Details
+            | Action::CancelDelete
+            | Action::SendToChat
+            | Action::ExportLinks
+            | Action::ExportText
+            | Action::ExportAria2
+            | Action::Stats
+            | Action::Recount
+            | Action::Select
+            | Action::SelectToggle
+            | Action::SelectPage
+            | Action::SelectDone
+            | Action::SelectCancelDelete
+            | Action::SelectLinks
+            | Action::SubmitDownload
+            | Action::PickOpen
+            | Action::PickFolder
+            | Action::PickPage
+            | Action::PickSave
+            | Action::PickRecent
+            | Action::PickCancel
+            | Action::Settings
+            | Action::Language
+            | Action::Noop => false,
+        }
+    }
+}
+
+// 按钮数据中的路径都是 StateStore::path_id 分配的 id，放在参数最后
+impl BrowseMode {
+    fn cd_data(&self, state: &StateStore, path: &str) -> String {
+        match self {
+            BrowseMode::Browse => Action::Open.data(state.path_id(path)),
+            BrowseMode::Pick(id) => {
+                Action::PickFolder.data(format!("{}:{}", id, state.path_id(path)))
+            }
+            BrowseMode::Trash => Action::TrashItem.data(state.path_id(path)),
+            BrowseMode::Select => Action::Noop.data(""),
+        }
+    }
+
+    fn page_data(&self, state: &StateStore, path: &str, page: u32) -> String {
+        match self {
+            BrowseMode::Browse => Action::Page.data(format!("{}:{}", page, state.path_id(path))),
+            BrowseMode::Pick(id) => Action::PickPage.data(format!("{}:{}", id, page)),
+            BrowseMode::Trash => Action::TrashPage.data(page),
+            BrowseMode::Select => Action::SelectPage.data(page),
+        }
+    }
+}
+
+// 批量解析链接时的并发数
+const RESOLVE_CONCURRENCY: usize = 4;
+// 等待相册其余消息到达的时间
+const MEDIA_GROUP_DELAY: Duration = Duration::from_secs(2);
+
+static MAGNET_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"magnet:\?[^\s<>"']+"#).unwrap());
+
 #[derive(BotCommands, Clone)]
 #[command(
     rename_rule = "lowercase",
     description = "These commands are supported:"
 )]
 pub enum Command {
-    #[command(description = "List files in the current or specified directory.")]
+    #[command(description = "Get started.")]
+    Start,
+    #[command(description = "Show the list of commands.")]
+    Help,
+    #[command(
+        rename = "ls",
+        alias = "list",
+        description = "List files in the current or specified directory."
+    )]
     List(String),
+    #[command(description = "Change the current directory.")]
+    Cd(String),
+    #[command(description = "Show the current directory.")]
+    Pwd,
+    #[command(description = "Get the direct link of a file.")]
+    Get(String),
+    #[command(description = "Browse the recycle bin.")]
+    Trash,
+    #[command(description = "Show downloads in progress.")]
+    Tasks,
+    #[command(description = "Show storage usage.")]
+    Quota,
+    #[command(description = "Switch the bot language (en, zh).")]
+    Lang(String),
+    #[command(description = "Change the settings of this chat.")]
+    Settings,
+}
+
+impl Command {
+    // 涉及整个云盘账号的命令只对管理员开放
+    fn admin_only(&self) -> bool {
+        matches!(self, Command::Trash | Command::Tasks | Command::Quota)
+    }
+}
+
+// 命令菜单: (命令, 说明, 是否仅管理员)
+const COMMANDS: [(&str, Msg, bool); 11] = [
+    ("start", Msg::CmdStart, false),
+    ("help", Msg::CmdHelp, false),
+    ("ls", Msg::CmdLs, false),
+    ("cd", Msg::CmdCd, false),
+    ("pwd", Msg::CmdPwd, false),
+    ("get", Msg::CmdGet, false),
+    ("trash", Msg::CmdTrash, true),
+    ("tasks", Msg::CmdTasks, true),
+    ("quota", Msg::CmdQuota, true),
+    ("lang", Msg::CmdLang, false),
+    ("settings", Msg::CmdSettings, false),
+];
+
+// ADMIN_USERS: 逗号分隔的用户 id，未设置时所有人都是管理员
+fn admin_ids() -> Vec<u64> {
+    env::var("ADMIN_USERS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|id| id.trim().parse::<u64>().ok())
+        .collect()
+}
+
+fn is_admin(user: Option<&User>) -> bool {
+    let admins = admin_ids();
+    admins.is_empty() || user.is_some_and(|user| admins.contains(&user.id.0))
+}
+
+fn bot_commands(lang: Lang, admin: bool) -> Vec<BotCommand> {
+    COMMANDS
+        .iter()
+        .filter(|(_, _, admin_only)| admin || !admin_only)
+        .map(|(command, description, _)| BotCommand::new(*command, description.text(lang)))
+        .collect()
+}
+
+fn help_text(lang: Lang, admin: bool) -> String {
+    let lines: Vec<String> = bot_commands(lang, admin)
+        .into_iter()
+        .map(|c| format!("/{} - {}", c.command, c.description))
+        .collect();
+    tr!(lang, HelpText, lines.join("\n"))
+}
+
+/// Registers the command menu with Telegram, in English and Chinese. Admins
+/// listed in `ADMIN_USERS` get the full menu in their private chat.
+pub async fn register_commands(bot: &Bot) {
+    let admins = admin_ids();
+    if admins.is_empty() {
+        warn!("ADMIN_USERS is not set: every user can delete, move and share files");
+    }
+    for lang in [Lang::En, Lang::Zh] {
+        let mut request = bot.set_my_commands(bot_commands(lang, admins.is_empty()));
+        // 英文菜单作为没有对应语言时的默认菜单
+        if lang == Lang::Zh {
+            request = request.language_code(lang.as_str());
+        }
+        if let Err(e) = request.await {
+            error!("Failed to register commands ({}): {}", lang.as_str(), e);
+        }
+        for &id in &admins {
+            let mut request =
+                bot.set_my_commands(bot_commands(lang, true))
+                    .scope(BotCommandScope::Chat {
+                        chat_id: Recipient::Id(ChatId(id as i64)),
+                    });
+            if lang == Lang::Zh {
+                request = request.language_code(lang.as_str());
+            }
+            if let Err(e) = request.await {
+                error!("Failed to register admin commands for {}: {}", id, e);
+            }
+        }
+    }
+}
+
+// 命令参数中的路径相对于当前目录；~ 表示浏览根目录
+async fn resolve_path(state: &StateStore, chat_id: ChatId, path: &str) -> String {
+    let path = path.trim();
+    if path == "~" || path.starts_with("~/") {
+        let root = browse_root(&state.chat_settings(chat_id).await);
+        return uri::resolve(&root, path[1..].trim_start_matches('/'));
+    }
+    uri::resolve(&current_dir(state, chat_id).await, path)
+}
+
+// 未用 /cd 切换过目录时为浏览根目录
+async fn current_dir(state: &StateStore, chat_id: ChatId) -> String {
+    match state.cwd(chat_id).await {
+        Some(cwd) => cwd,
+        None => browse_root(&state.chat_settings(chat_id).await),
+    }
+}
+
+// 正在下载的任务的一行摘要: 名称、已下载/总大小和进度
+fn task_line(task: &Value, lang: Lang) -> String {
+    let download = |key: &str| {
+        task.pointer("/summary/props/download")
+            .and_then(|d| d.get(key))
+            .and_then(|v| v.as_u64())
+            .unwrap_or_default()
+    };
+    let (downloaded, total) = (download("downloaded"), download("total"));
+    let progress = if total > 0 {
+        downloaded as f64 * 100.0 / total as f64
+    } else {
+        0.0
+    };
+    tr!(
+        lang,
+        TaskLine,
+        html::escape(task_name(task).unwrap_or(&tr!(lang, Unknown))),
+        format_size(downloaded),
+        format_size(total),
+        format!("{:.1}", progress)
+    )
 }
 
 // Handler for commands
@@ -42,113 +456,720 @@ pub async fn answer(
     cmd: Command,
     client: Arc<CloudreveClient>,
     cache: PageTokenCache,
+    state: StateStore,
 ) -> ResponseResult<()> {
+    let lang = state.lang(msg.from.as_ref()).await;
+    let admin = is_admin(msg.from.as_ref());
+    if cmd.admin_only() && !admin {
+        bot.send_message(msg.chat.id, tr!(lang, AdminOnly))
+            .reply_parameters(ReplyParameters::new(msg.id))
+            .await?;
+        return Ok(());
+    }
     match cmd {
+        Command::Start => {
+            bot.send_message(msg.chat.id, tr!(lang, Welcome, help_text(lang, admin)))
+                .await?;
+        }
+        Command::Help => {
+            bot.send_message(msg.chat.id, help_text(lang, admin))
+                .await?;
+        }
         Command::List(path) => {
+            let path = resolve_path(&state, msg.chat.id, &path).await;
+
+            let page = 0;
+            list_files_and_send(
+                &bot,
+                msg.chat.id,
+                &client,
+                &path,
+                page,
+                cache,
+                &state,
+                None,
+                BrowseMode::Browse,
+                lang,
+            )
+            .await?;
+        }
+        Command::Cd(path) => {
             let path = path.trim();
             let path = if path.is_empty() {
-                env::var("CLOUDEREVE_BASE_PATH").unwrap_or_else(|_| "cloudreve://my".to_string())
+                browse_root(&state.chat_settings(msg.chat.id).await)
             } else {
-                path.to_string()
+                resolve_path(&state, msg.chat.id, path).await
             };
-
-            let page = 0;
-            list_files_and_send(&bot, msg.chat.id, &client, &path, page, cache, None).await?;
+            let shown = html::escape(&uri::decode(&path));
+            let text = match client.get_file_info(&path).await {
+                Ok(info) if info.get("type").and_then(|v| v.as_i64()) == Some(1) => {
+                    state.set_cwd(msg.chat.id, &path).await;
+                    tr!(lang, Cwd, shown)
+                }
+                Ok(_) => tr!(lang, NotAFolder, shown),
+                Err(e) => tr!(lang, OpenFolderFailed, shown, html::escape(&e.to_string())),
+            };
+            bot.send_message(msg.chat.id, text)
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .await?;
+        }
+        Command::Pwd => {
+            let cwd = current_dir(&state, msg.chat.id).await;
+            bot.send_message(
+                msg.chat.id,
+                tr!(lang, Cwd, html::escape(&uri::decode(&cwd))),
+            )
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .await?;
+        }
+        Command::Get(path) => {
+            let path = path.trim();
+            if path.is_empty() {
+                bot.send_message(msg.chat.id, tr!(lang, GetUsage)).await?;
+                return Ok(());
+            }
+            let path = resolve_path(&state, msg.chat.id, path).await;
+            let mut request = match client.file_source(&path, DOWNLOAD_LINK).await {
+                Ok(source) => bot
+                    .send_message(msg.chat.id, link_text(&source, lang))
+                    .reply_markup(link_keyboard(&state, &path, lang)),
+                Err(e) => bot.send_message(
+                    msg.chat.id,
+                    tr!(lang, GetLinkFailed, html::escape(&e.to_string())),
+                ),
+            };
+            request = request.parse_mode(teloxide::types::ParseMode::Html);
+            request.await?;
+        }
+        Command::Tasks => {
+            let text = match client.remote_list("downloading").await {
+                Ok(data) => {
+                    let tasks = data
+                        .get("tasks")
+                        .and_then(|t| t.as_array())
+                        .cloned()
+                        .unwrap_or_default();
+                    if tasks.is_empty() {
+                        tr!(lang, NoTasks)
+                    } else {
+                        let lines: Vec<String> =
+                            tasks.iter().map(|task| task_line(task, lang)).collect();
+                        tr!(lang, Tasks, tasks.len(), lines.join("\n\n"))
+                    }
+                }
+                Err(e) => tr!(lang, TasksFailed, html::escape(&e.to_string())),
+            };
+            bot.send_message(msg.chat.id, text)
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .await?;
+        }
+        Command::Quota => {
+            let text = match client.capacity().await {
+                Ok(capacity) => {
+                    let percent = if capacity.total > 0 {
+                        capacity.used as f64 * 100.0 / capacity.total as f64
+                    } else {
+                        0.0
+                    };
+                    tr!(
+                        lang,
+                        Quota,
+                        format_size(capacity.used),
+                        format_size(capacity.total),
+                        format!("{:.1}", percent),
+                        format_size(capacity.total.saturating_sub(capacity.used))
+                    )
+                }
+                Err(e) => tr!(lang, QuotaFailed, e),
+            };
+            bot.send_message(msg.chat.id, text).await?;
+        }
+        Command::Trash => {
+            list_files_and_send(
+                &bot,
+                msg.chat.id,
+                &client,
+                uri::TRASH,
+                0,
+                cache,
+                &state,
+                None,
+                BrowseMode::Trash,
+                lang,
+            )
+            .await?;
+        }
+        Command::Lang(code) => {
+            let code = code.trim();
+            if code.is_empty() {
+                bot.send_message(msg.chat.id, tr!(lang, LangCurrent))
+                    .reply_markup(lang_keyboard())
+                    .await?;
+            } else if let (Some(new_lang), Some(user)) = (Lang::parse(code), msg.from.as_ref()) {
+                state.set_lang(user.id, new_lang).await;
+                bot.send_message(msg.chat.id, tr!(new_lang, LangSet))
+                    .await?;
+            } else {
+                bot.send_message(msg.chat.id, tr!(lang, LangUnsupported, code))
+                    .await?;
+            }
+        }
+        Command::Settings => {
+            let settings = state.chat_settings(msg.chat.id).await;
+            let options = state.list_options(msg.chat.id).await;
+            bot.send_message(msg.chat.id, settings_text(&settings, lang))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_markup(settings_keyboard(&settings, &options, lang))
+                .await?;
         }
     };
     Ok(())
 }
 
+fn lang_keyboard() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback("中文", Action::Language.data(Lang::Zh.as_str())),
+        InlineKeyboardButton::callback("English", Action::Language.data(Lang::En.as_str())),
+    ]])
+}
+
+// 每页条目数的可选值，设置按钮依次切换
+const PAGE_SIZES: [u32; 4] = [5, 10, 20, 50];
+
+// 未设置时 /list 打开 CLOUDEREVE_BASE_PATH
+fn browse_root(settings: &ChatSettings) -> String {
+    settings.root.clone().unwrap_or_else(|| {
+        env::var("CLOUDEREVE_BASE_PATH").unwrap_or_else(|_| "cloudreve://my".to_string())
+    })
+}
+
+fn download_path(settings: &ChatSettings) -> String {
+    settings
+        .download_dst
+        .clone()
+        .unwrap_or_else(default_download_path)
+}
+
+fn on_off(value: bool, lang: Lang) -> String {
+    if value {
+        tr!(lang, SettingOn)
+    } else {
+        tr!(lang, SettingOff)
+    }
+}
+
+fn settings_text(settings: &ChatSettings, lang: Lang) -> String {
+    tr!(
+        lang,
+        SettingsText,
+        html::escape(&uri::decode(&browse_root(settings))),
+        html::escape(&uri::decode(&download_path(settings)))
+    )
+}
+
+// 设置菜单，每个按钮切换一项: cs:<key>
+fn settings_keyboard(
+    settings: &ChatSettings,
+    options: &ListOptions,
+    lang: Lang,
+) -> InlineKeyboardMarkup {
+    let button =
+        |text: String, key: &str| InlineKeyboardButton::callback(text, Action::Settings.data(key));
+    InlineKeyboardMarkup::new(vec![
+        vec![
+            button(tr!(lang, BtnPageSize, settings.page_size), "page"),
+            button(tr!(lang, BtnLanguage), "lang"),
+        ],
+        vec![
+            button(
+                tr!(lang, SortButton, order_by_label(options.order_by, lang)),
+                "sort",
+            ),
+            button(direction_label(options.order_direction, lang), "dir"),
+        ],
+        vec![
+            button(
+                tr!(lang, BtnNotify, on_off(settings.notify, lang)),
+                "notify",
+            ),
+            button(
+                tr!(lang, BtnAutoOffer, on_off(settings.auto_offer, lang)),
+                "offer",
+            ),
+        ],
+        vec![
+            button(tr!(lang, BtnBrowseRoot), "root"),
+            button(tr!(lang, BtnDownloadDestination), "dst"),
+        ],
+        vec![button(tr!(lang, BtnResetSettings), "reset")],
+    ])
+}
+
+// 未出现在下载列表中的任务最多等待的轮询次数
+const STATUS_DISCOVERY_POLLS: u32 = 12;
+// 任务状态的轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+// 同时跟踪的任务数上限，以及每个任务最长跟踪多久
+const MAX_TRACKERS: usize = 16;
+const TRACK_DEADLINE: Duration = Duration::from_secs(6 * 60 * 60);
+
+static TRACKERS: LazyLock<Semaphore> = LazyLock::new(|| Semaphore::new(MAX_TRACKERS));
+
+// 下载和后台任务共用的跟踪限制：没有空闲名额时不跟踪，显示 busy；
+// 超过 TRACK_DEADLINE 后停止，显示 stopped
+async fn run_tracker(
+    bot: &Bot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    tracker: impl Future<Output = Result<()>>,
+    busy: String,
+    stopped: String,
+) -> Result<()> {
+    let Ok(_permit) = TRACKERS.try_acquire() else {
+        bot.edit_message_text(chat_id, message_id, busy).await?;
+        return Ok(());
+    };
+    match timeout(TRACK_DEADLINE, tracker).await {
+        Ok(result) => result,
+        Err(_) => {
+            bot.edit_message_text(chat_id, message_id, stopped).await?;
+            Ok(())
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn get_download_status(
     bot: Bot,
     chat_id: ChatId,
     message_id: MessageId,
-    url: &str,
+    urls: &[String],
     client: Arc<CloudreveClient>,
+    state: &StateStore,
+    lang: Lang,
+    notify: bool,
 ) -> Result<()> {
-    let url = url.to_string();
-
     // Send initial message
     let status_msg = bot
-        .edit_message_text(chat_id, message_id, "正在获取下载状态...")
+        .edit_message_text(chat_id, message_id, tr!(lang, FetchingDownloadStatus))
         .await?;
 
     let chat_id = status_msg.chat.id;
     let message_id = status_msg.id;
 
-    loop {
-        sleep(Duration::from_secs(5)).await;
-        bot.edit_message_text(chat_id, message_id, "正在获取下载状态...")
-            .await?;
-        match client.search_remote_list_by_url("downloading", &url).await {
-            Ok(resp) => {
-                let name = resp
-                    .get("name")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Unknown");
-                let size_str = resp
-                    .get("size_str")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Unknown");
-                let progress_str = resp
-                    .get("progress_str")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("0");
+    let started = Instant::now();
+    // 每个任务最近一次的状态文本，以及是否已离开下载队列
+    let mut lines: Vec<String> = urls
+        .iter()
+        .map(|url| tr!(lang, DownloadWaiting, html::escape(url)))
+        .collect();
+    let mut seen = vec![false; urls.len()];
+    let mut finished = vec![false; urls.len()];
+    let mut polls = 0;
 
-                let text = format!(
-                    "<b>{}</b>\n文件大小: {}\n下载进度: {}%",
-                    name, size_str, progress_str
-                );
+    loop {
+        sleep(POLL_INTERVAL).await;
+        polls += 1;
+        for (i, url) in urls.iter().enumerate() {
+            if finished[i] {
+                continue;
+            }
+            match client.search_remote_list_by_url("downloading", url).await {
+                Ok(resp) => {
+                    seen[i] = true;
+                    let unknown = tr!(lang, Unknown);
+                    let name = resp
+                        .get("name")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or(&unknown);
+                    let size_str = resp
+                        .get("size")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or(&unknown);
+                    let progress_str = resp.get("progress").and_then(|v| v.as_str()).unwrap_or("0");
 
-                // Edit the message
-                match bot
-                    .edit_message_text(chat_id, message_id, text)
-                    .parse_mode(teloxide::types::ParseMode::Html)
-                    .await
-                {
-                    Ok(_) => {}
-                    Err(e) => {
-                        // Ignore "message is not modified" errors, log others
-                        if !e.to_string().contains("message is not modified") {
-                            info!("Failed to update status message: {}", e);
+                    lines[i] = tr!(
+                        lang,
+                        DownloadProgress,
+                        html::escape(name),
+                        size_str,
+                        progress_str
+                    );
+                }
+                Err(e) => {
+                    info!("搜索下载失败: {}", e);
+                    // 任务离开下载队列后，到已完成列表中确认最终状态
+                    match client.find_task_by_src("downloaded", url).await {
+                        Ok(Some(task)) => {
+                            finished[i] = true;
+                            lines[i] = finished_task_line(&task, url, lang);
+                            if let Err(e) = notify_download_finished(
+                                &bot,
+                                state,
+                                chat_id,
+                                message_id,
+                                url,
+                                &task,
+                                started.elapsed(),
+                                lang,
+                                notify,
+                            )
+                            .await
+                            {
+                                info!("Failed to send download notification: {}", e);
+                            }
+                        }
+                        _ if seen[i] => {
+                            finished[i] = true;
+                            lines[i].push('\n');
+                            lines[i].push_str(&tr!(lang, DownloadEnded));
+                        }
+                        _ if polls >= STATUS_DISCOVERY_POLLS => {
+                            finished[i] = true;
+                            lines[i] = tr!(lang, DownloadNotFound, html::escape(url));
                         }
+                        _ => {}
                     }
                 }
+            }
+        }
+
+        let done = finished.iter().filter(|f| **f).count();
+        let text = if urls.len() > 1 {
+            tr!(
+                lang,
+                DownloadsFinished,
+                done,
+                urls.len(),
+                lines.join("\n\n")
+            )
+        } else {
+            lines.join("\n\n")
+        };
 
-                if progress_str == "100" {
-                    break;
+        // Edit the message
+        match bot
+            .edit_message_text(chat_id, message_id, text)
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => {
+                // Ignore "message is not modified" errors, log others
+                if !e.to_string().contains("message is not modified") {
+                    info!("Failed to update status message: {}", e);
                 }
             }
+        }
+
+        if done == urls.len() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// 已结束任务在状态消息中的一行摘要
+fn finished_task_line(task: &Value, url: &str, lang: Lang) -> String {
+    let name = html::escape(task_name(task).unwrap_or(url));
+    match task.get("status").and_then(|v| v.as_str()) {
+        Some("completed") => tr!(lang, DownloadCompletedLine, name),
+        Some("error") => tr!(lang, DownloadFailedLine, name),
+        _ => tr!(lang, DownloadEndedLine, name),
+    }
+}
+
+fn task_name(task: &Value) -> Option<&str> {
+    task.pointer("/summary/props/download/name")
+        .and_then(|v| v.as_str())
+        .filter(|name| !name.is_empty())
+}
+
+pub fn format_duration(duration: Duration, lang: Lang) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    if hours > 0 {
+        tr!(lang, DurationHours, hours, minutes, seconds)
+    } else if minutes > 0 {
+        tr!(lang, DurationMinutes, minutes, seconds)
+    } else {
+        tr!(lang, DurationSeconds, seconds)
+    }
+}
+
+// 下载结束后发送一条新消息，让 Telegram 提醒用户
+#[allow(clippy::too_many_arguments)]
+async fn notify_download_finished(
+    bot: &Bot,
+    state: &StateStore,
+    chat_id: ChatId,
+    status_message_id: MessageId,
+    url: &str,
+    task: &Value,
+    elapsed: Duration,
+    lang: Lang,
+    notify: bool,
+) -> Result<()> {
+    let name = task_name(task).unwrap_or(url);
+    let download = task.pointer("/summary/props/download");
+    let (text, keyboard) = match task.get("status").and_then(|v| v.as_str()) {
+        Some("completed") => {
+            let dst = task
+                .pointer("/summary/props/dst")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            let path = uri::join(dst, name);
+            let size = download
+                .and_then(|d| d.get("total").or_else(|| d.get("size")))
+                .and_then(|v| v.as_u64());
+            // 多文件的种子任务会保存为一个目录
+            let is_folder = download
+                .and_then(|d| d.get("files"))
+                .and_then(|v| v.as_array())
+                .map(|files| files.len() > 1)
+                .unwrap_or(false);
+            let text = tr!(
+                lang,
+                DownloadCompleted,
+                html::escape(name),
+                html::escape(&uri::decode(dst)),
+                html::escape(name),
+                size.map_or(tr!(lang, Unknown), format_size),
+                format_duration(elapsed, lang)
+            );
+            let keyboard = if is_folder {
+                vec![vec![
+                    path_button(state, tr!(lang, BtnOpenFolder), Action::Open, &path),
+                    path_button(state, tr!(lang, BtnShare), Action::Share, &path),
+                ]]
+            } else {
+                vec![
+                    vec![
+                        path_button(state, tr!(lang, BtnOpenFolder), Action::Open, dst),
+                        path_button(state, tr!(lang, BtnGetLink), Action::GetLink, &path),
+                    ],
+                    vec![
+                        path_button(state, tr!(lang, BtnShare), Action::Share, &path),
+                        path_button(state, tr!(lang, BtnSendToChat), Action::SendToChat, &path),
+                    ],
+                ]
+            };
+            (text, Some(InlineKeyboardMarkup::new(keyboard)))
+        }
+        _ => {
+            let reason = task
+                .get("error")
+                .and_then(|v| v.as_str())
+                .filter(|e| !e.is_empty())
+                .map(|e| e.to_string())
+                .unwrap_or_else(|| tr!(lang, Unknown));
+            let text = tr!(
+                lang,
+                DownloadFailed,
+                html::escape(name),
+                html::escape(&reason)
+            );
+            (text, None)
+        }
+    };
+
+    let mut request = bot
+        .send_message(chat_id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_parameters(ReplyParameters::new(status_message_id))
+        .disable_notification(!notify);
+    if let Some(keyboard) = keyboard {
+        request = request.reply_markup(keyboard);
+    }
+    request.await?;
+    Ok(())
+}
+
+// 跟踪压缩、解压等后台任务，结束后发送新消息提醒
+#[allow(clippy::too_many_arguments)]
+pub async fn track_workflow(
+    bot: Bot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    task_id: &str,
+    title: &str,
+    open_uri: &str,
+    client: Arc<CloudreveClient>,
+    state: &StateStore,
+    lang: Lang,
+    notify: bool,
+) -> Result<()> {
+    let started = Instant::now();
+    let mut polls = 0;
+    loop {
+        sleep(POLL_INTERVAL).await;
+        polls += 1;
+        let task = match client.find_task_by_id("general", task_id).await {
+            Ok(Some(task)) => task,
+            Ok(None) if polls < STATUS_DISCOVERY_POLLS => continue,
+            Ok(None) => {
+                bot.edit_message_text(chat_id, message_id, tr!(lang, WorkflowNotFound, title))
+                    .await?;
+                return Ok(());
+            }
             Err(e) => {
-                info!("搜索下载失败: {}", e);
+                info!("Failed to get task {}: {}", task_id, e);
+                continue;
+            }
+        };
+        let status = task
+            .get("status")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        match status {
+            "completed" => {
+                bot.edit_message_text(chat_id, message_id, tr!(lang, WorkflowCompleted, title))
+                    .await?;
+                let keyboard = InlineKeyboardMarkup::new(vec![vec![path_button(
+                    state,
+                    tr!(lang, BtnOpenFolder),
+                    Action::Open,
+                    open_uri,
+                )]]);
+                bot.send_message(
+                    chat_id,
+                    tr!(
+                        lang,
+                        WorkflowCompletedNotice,
+                        title,
+                        html::escape(&uri::decode(open_uri)),
+                        format_duration(started.elapsed(), lang)
+                    ),
+                )
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_parameters(ReplyParameters::new(message_id))
+                .reply_markup(keyboard)
+                .disable_notification(!notify)
+                .await?;
+                return Ok(());
+            }
+            "error" | "canceled" => {
+                let reason = task
+                    .get("error")
+                    .and_then(|v| v.as_str())
+                    .filter(|e| !e.is_empty())
+                    .unwrap_or(status);
+                bot.edit_message_text(chat_id, message_id, tr!(lang, WorkflowFailed, title))
+                    .await?;
+                bot.send_message(chat_id, tr!(lang, WorkflowFailedNotice, title, reason))
+                    .reply_parameters(ReplyParameters::new(message_id))
+                    .disable_notification(!notify)
+                    .await?;
+                return Ok(());
+            }
+            _ => {
+                let text = tr!(
+                    lang,
+                    WorkflowRunning,
+                    title,
+                    status,
+                    format_duration(started.elapsed(), lang)
+                );
+                if let Err(e) = bot.edit_message_text(chat_id, message_id, text).await {
+                    if !e.to_string().contains("message is not modified") {
+                        info!("Failed to update status message: {}", e);
+                    }
+                }
             }
         }
     }
+}
+
+// 提交压缩/解压任务，并在新消息中跟踪进度
+#[allow(clippy::too_many_arguments)]
+async fn submit_workflow(
+    bot: &Bot,
+    chat_id: ChatId,
+    reply_to: MessageId,
+    title: String,
+    open_uri: String,
+    client: Arc<CloudreveClient>,
+    state: &StateStore,
+    task: Result<Value>,
+    lang: Lang,
+    notify: bool,
+) -> ResponseResult<()> {
+    let task_id = match task {
+        Ok(task) => crate::cloudreve::task_id(&task),
+        Err(e) => {
+            bot.send_message(chat_id, tr!(lang, WorkflowSubmitFailed, title, e))
+                .reply_parameters(ReplyParameters::new(reply_to))
+                .await?;
+            return Ok(());
+        }
+    };
+    let status_msg = bot
+        .send_message(chat_id, tr!(lang, WorkflowSubmitted, title))
+        .reply_parameters(ReplyParameters::new(reply_to))
+        .await?;
+    if let Some(task_id) = task_id {
+        let bot = bot.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            let tracker = track_workflow(
+                bot.clone(),
+                chat_id,
+                status_msg.id,
+                &task_id,
+                &title,
+                &open_uri,
+                client,
+                &state,
+                lang,
+                notify,
+            );
+            let result = run_tracker(
+                &bot,
+                chat_id,
+                status_msg.id,
+                tracker,
+                tr!(lang, WorkflowNotTracked, title),
+                tr!(lang, WorkflowUntracked, title),
+            )
+            .await;
+            if let Err(e) = result {
+                error!("Failed to track task {}: {}", task_id, e);
+            }
+        });
+    }
     Ok(())
 }
 
+// callback data 最长 64 字节，路径以 StateStore 中的 id 代替: <action>:<id>
+fn path_button(
+    state: &StateStore,
+    text: impl Into<String>,
+    action: Action,
+    path: &str,
+) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback(text, action.data(state.path_id(path)))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn send_remote_download(
     bot: Bot,
     msg: Message,
-    url: &str,
+    urls: &[String],
+    dst: &str,
     client: Arc<CloudreveClient>,
+    state: &StateStore,
+    lang: Lang,
+    notify: bool,
 ) -> Result<()> {
-    match client.remote_download(url).await {
+    match client.remote_download(urls, dst).await {
         Ok(_) => {
             let sent_msg = bot
-                .edit_message_text(msg.chat.id, msg.id, "远程下载成功".to_string())
+                .edit_message_text(msg.chat.id, msg.id, tr!(lang, RemoteDownloadSubmitted))
                 .await?;
-            let _ = get_download_status(
-                bot.clone(),
-                sent_msg.chat.id,
-                sent_msg.id,
-                url,
-                client.clone(),
-            )
-            .await;
+            spawn_download_tracker(bot, &sent_msg, urls.to_vec(), client, state, lang, notify);
         }
         Err(e) => {
-            bot.send_message(msg.chat.id, format!("远程下载失败: {}", e))
+            bot.send_message(msg.chat.id, tr!(lang, RemoteDownloadFailed, e))
                 .reply_parameters(ReplyParameters::new(msg.id))
                 .await?;
         }
@@ -156,30 +1177,724 @@ pub async fn send_remote_download(
     Ok(())
 }
 
-// Handler for callback queries (button clicks)
-pub async fn callback_handler(
+// 在后台跟踪下载进度；同一聊天的消息按顺序处理，在处理函数中等待会阻塞该聊天
+fn spawn_download_tracker(
     bot: Bot,
-    q: CallbackQuery,
+    status_msg: &Message,
+    urls: Vec<String>,
     client: Arc<CloudreveClient>,
-    cache: PageTokenCache,
-) -> ResponseResult<()> {
-    if let Some(data) = q.data {
-        let parts: Vec<&str> = data.splitn(2, ':').collect();
-        if parts.len() < 2 {
-            return Ok(());
+    state: &StateStore,
+    lang: Lang,
+    notify: bool,
+) {
+    let (chat_id, message_id) = (status_msg.chat.id, status_msg.id);
+    let state = state.clone();
+    tokio::spawn(async move {
+        let status = get_download_status(
+            bot.clone(),
+            chat_id,
+            message_id,
+            &urls,
+            client,
+            &state,
+            lang,
+            notify,
+        );
+        let result = run_tracker(
+            &bot,
+            chat_id,
+            message_id,
+            status,
+            tr!(lang, DownloadsNotTracked),
+            tr!(lang, DownloadsUntracked),
+        )
+        .await;
+        if let Err(e) = result {
+            error!("Failed to track downloads in chat {}: {}", chat_id, e);
         }
-        let action = parts[0];
-        let payload = parts[1];
+    });
+}
 
-        bot.answer_callback_query(q.id)
-            .send()
-            .await
-            .log_on_error()
+#[allow(clippy::too_many_arguments)]
+pub async fn send_torrent_download(
+    bot: Bot,
+    msg: Message,
+    file_id: &str,
+    file_name: &str,
+    dst: &str,
+    client: Arc<CloudreveClient>,
+    state: &StateStore,
+    lang: Lang,
+    notify: bool,
+) -> Result<()> {
+    bot.edit_message_text(msg.chat.id, msg.id, tr!(lang, UploadingTorrent))
+        .await?;
+
+    let submitted = async {
+        let uri = upload_telegram_file(&bot, &client, file_id, file_name, dst).await?;
+        client.remote_download_torrent(&uri, dst).await?;
+        Ok::<_, anyhow::Error>(uri)
+    }
+    .await;
+
+    match submitted {
+        Ok(uri) => {
+            let sent_msg = bot
+                .edit_message_text(msg.chat.id, msg.id, tr!(lang, RemoteDownloadSubmitted))
+                .await?;
+            spawn_download_tracker(bot, &sent_msg, vec![uri], client, state, lang, notify);
+        }
+        Err(e) => {
+            bot.send_message(msg.chat.id, tr!(lang, RemoteDownloadFailed, e))
+                .reply_parameters(ReplyParameters::new(msg.id))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+// 先把文件从 Telegram 下载下来，再上传到保存目录，返回其云盘 uri
+async fn upload_telegram_file(
+    bot: &Bot,
+    client: &CloudreveClient,
+    file_id: &str,
+    file_name: &str,
+    dst: &str,
+) -> Result<String> {
+    let file = bot.get_file(file_id).await?;
+    let mut content = Vec::new();
+    bot.download_file(&file.path, &mut content).await?;
+    let uri = uri::join(dst, file_name);
+    client.upload_file(&uri, content).await?;
+    Ok(uri)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn send_file_ingest(
+    bot: Bot,
+    msg: Message,
+    files: &[TelegramFile],
+    dst: &str,
+    client: Arc<CloudreveClient>,
+    state: &StateStore,
+    lang: Lang,
+) -> Result<()> {
+    bot.edit_message_text(msg.chat.id, msg.id, tr!(lang, UploadingFile))
+        .await?;
+
+    // 相册中的文件逐个上传，失败的文件单独报告
+    let mut saved = Vec::new();
+    let mut failed = Vec::new();
+    for file in files {
+        match upload_telegram_file(&bot, &client, &file.file_id, &file.file_name, dst).await {
+            Ok(uri) => saved.push(uri),
+            Err(e) => failed.push(format!("{}: {}", file.file_name, e)),
+        }
+    }
+    if let [uri] = saved.as_slice() {
+        let text = tr!(lang, FileSaved, html::escape(&uri::decode(uri)));
+        let keyboard = InlineKeyboardMarkup::new(vec![vec![
+            path_button(state, tr!(lang, BtnOpenFolder), Action::Open, dst),
+            path_button(state, tr!(lang, BtnGetLink), Action::GetLink, uri),
+        ]]);
+        bot.edit_message_text(msg.chat.id, msg.id, text)
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_markup(keyboard)
+            .await?;
+    } else if !saved.is_empty() {
+        let text = tr!(
+            lang,
+            FilesSaved,
+            saved.len(),
+            html::escape(&uri::decode(dst))
+        );
+        let keyboard = InlineKeyboardMarkup::new(vec![vec![path_button(
+            state,
+            tr!(lang, BtnOpenFolder),
+            Action::Open,
+            dst,
+        )]]);
+        bot.edit_message_text(msg.chat.id, msg.id, text)
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_markup(keyboard)
+            .await?;
+    }
+    if !failed.is_empty() {
+        bot.send_message(msg.chat.id, tr!(lang, SaveFileFailed, failed.join("\n")))
+            .reply_parameters(ReplyParameters::new(msg.id))
+            .await?;
+    }
+    Ok(())
+}
+
+pub fn default_download_path() -> String {
+    env::var("CLOUDEREVE_DOWNLOAD_PATH")
+        .or_else(|_| env::var("CLOUDEREVE_BASE_PATH"))
+        .unwrap_or_else(|_| "cloudreve://my".to_string())
+}
+
+// 提交按钮、选择目录按钮，以及该用户最近使用过的保存目录
+pub async fn download_keyboard(
+    state: &StateStore,
+    id: u64,
+    user: Option<UserId>,
+    lang: Lang,
+) -> InlineKeyboardMarkup {
+    let mut buttons = vec![vec![
+        InlineKeyboardButton::callback(
+            tr!(lang, BtnSubmitDownload),
+            Action::SubmitDownload.data(id),
+        ),
+        InlineKeyboardButton::callback(tr!(lang, BtnChooseFolder), Action::PickOpen.data(id)),
+    ]];
+    if let Some(user) = user {
+        // 按钮记录目录本身而不是序号，最近列表变化后仍指向原目录
+        for dst in state.recent_destinations(user).await {
+            buttons.push(vec![InlineKeyboardButton::callback(
+                format!("🕘 {}", uri::display_name(&dst)),
+                Action::PickRecent.data(format!("{}:{}", id, state.path_id(&dst))),
+            )]);
+        }
+    }
+    InlineKeyboardMarkup::new(buttons)
+}
+
+pub async fn submit_pending(
+    bot: Bot,
+    msg: Message,
+    pending: Pending,
+    user: UserId,
+    client: Arc<CloudreveClient>,
+    state: &StateStore,
+    lang: Lang,
+) -> Result<()> {
+    let mut settings = state.chat_settings(msg.chat.id).await;
+    let dst = pending.dst.unwrap_or_else(|| download_path(&settings));
+    let notify = settings.notify;
+    match pending.action {
+        PendingAction::Download(source) => {
+            state.add_recent_destination(user, &dst).await;
+            match source {
+                DownloadSource::Url(url) => {
+                    send_remote_download(bot, msg, &[url], &dst, client, state, lang, notify).await
+                }
+                DownloadSource::Batch(urls) => {
+                    send_remote_download(bot, msg, &urls, &dst, client, state, lang, notify).await
+                }
+                DownloadSource::Torrent { file_id, file_name } => {
+                    send_torrent_download(
+                        bot, msg, &file_id, &file_name, &dst, client, state, lang, notify,
+                    )
+                    .await
+                }
+                DownloadSource::TelegramFiles(files) => {
+                    send_file_ingest(bot, msg, &files, &dst, client, state, lang).await
+                }
+            }
+        }
+        PendingAction::Move { uris, copy } => {
+            let text = match client.move_files(&uris, &dst, copy).await {
+                Ok(()) => {
+                    let dst = html::escape(&uri::decode(&dst));
+                    if copy {
+                        tr!(lang, FilesCopied, uris.len(), dst)
+                    } else {
+                        tr!(lang, FilesMoved, uris.len(), dst)
+                    }
+                }
+                Err(e) => tr!(lang, OperationFailed, html::escape(&e.to_string())),
+            };
+            bot.edit_message_text(msg.chat.id, msg.id, text)
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .await?;
+            Ok(())
+        }
+        PendingAction::SetRoot | PendingAction::SetDownloadDestination => {
+            let text = if matches!(pending.action, PendingAction::SetRoot) {
+                settings.root = Some(dst.clone());
+                tr!(lang, RootSet, html::escape(&uri::decode(&dst)))
+            } else {
+                settings.download_dst = Some(dst.clone());
+                tr!(
+                    lang,
+                    DownloadDestinationSet,
+                    html::escape(&uri::decode(&dst))
+                )
+            };
+            state.set_chat_settings(msg.chat.id, settings).await;
+            bot.edit_message_text(msg.chat.id, msg.id, text)
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+// 根据扩展名推断 MIME 类型
+pub fn guess_mime(name: &str) -> &'static str {
+    let ext = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "mp4" | "m4v" => "video/mp4",
+        "mkv" => "video/x-matroska",
+        "avi" => "video/x-msvideo",
+        "mov" => "video/quicktime",
+        "webm" => "video/webm",
+        "ts" => "video/mp2t",
+        "mp3" => "audio/mpeg",
+        "flac" => "audio/flac",
+        "wav" => "audio/wav",
+        "m4a" | "aac" => "audio/aac",
+        "ogg" | "opus" => "audio/ogg",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "heic" => "image/heic",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "rar" => "application/vnd.rar",
+        "7z" => "application/x-7z-compressed",
+        "tar" => "application/x-tar",
+        "gz" | "tgz" => "application/gzip",
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "json" => "application/json",
+        "html" | "htm" => "text/html",
+        "doc" | "docx" => "application/msword",
+        "xls" | "xlsx" => "application/vnd.ms-excel",
+        "ppt" | "pptx" => "application/vnd.ms-powerpoint",
+        "epub" => "application/epub+zip",
+        "apk" => "application/vnd.android.package-archive",
+        "iso" => "application/x-iso9660-image",
+        "torrent" => "application/x-bittorrent",
+        _ => "application/octet-stream",
+    }
+}
+
+// 根据扩展名选择列表图标
+fn file_icon(name: &str) -> &'static str {
+    let mime = guess_mime(name);
+    match mime.split('/').next().unwrap_or_default() {
+        "video" => "🎬",
+        "audio" => "🎵",
+        "image" => "🖼",
+        _ if is_archive(name) => "📦",
+        _ => match mime {
+            "application/pdf"
+            | "application/msword"
+            | "application/vnd.ms-excel"
+            | "application/vnd.ms-powerpoint"
+            | "application/epub+zip"
+            | "text/plain"
+            | "text/markdown" => "📝",
+            _ => "📄",
+        },
+    }
+}
+
+// "2024-05-01T12:34:56.789+08:00" -> "2024-05-01 12:34"
+pub fn format_time(time: &str) -> String {
+    time.get(..16).unwrap_or(time).replacen('T', " ", 1)
+}
+
+fn file_details_text(info: &Value, lang: Lang) -> String {
+    let unknown = tr!(lang, Unknown);
+    let name = info
+        .get("name")
+        .and_then(|v| v.as_str())
+        .unwrap_or(&unknown);
+    let size = info
+        .get("size")
+        .and_then(|v| v.as_u64())
+        .unwrap_or_default();
+    let created = info
+        .get("created_at")
+        .and_then(|v| v.as_str())
+        .map(format_time)
+        .unwrap_or_default();
+    let updated = info
+        .get("updated_at")
+        .and_then(|v| v.as_str())
+        .map(format_time)
+        .unwrap_or_default();
+    let owner = info
+        .pointer("/extended_info/entities/0/created_by/nickname")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| {
+            let owned = info.get("owned").and_then(|v| v.as_bool()).unwrap_or(false);
+            if owned {
+                tr!(lang, Me)
+            } else {
+                unknown.clone()
+            }
+        });
+    let policy = info
+        .pointer("/extended_info/storage_policy/name")
+        .and_then(|v| v.as_str())
+        .unwrap_or(&unknown);
+    let shares = info
+        .pointer("/extended_info/shares")
+        .and_then(|v| v.as_array())
+        .map(|shares| shares.len())
+        .unwrap_or_default();
+    let shared = info
+        .get("shared")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let share_status = if shares > 0 {
+        tr!(lang, SharedCount, shares)
+    } else if shared {
+        tr!(lang, Shared)
+    } else {
+        tr!(lang, NotShared)
+    };
+
+    tr!(
+        lang,
+        FileDetails,
+        html::escape(name),
+        format_size(size),
+        guess_mime(name),
+        created,
+        updated,
+        html::escape(&owner),
+        html::escape(policy),
+        share_status
+    )
+}
+
+fn file_details_keyboard(state: &StateStore, path: &str, lang: Lang) -> InlineKeyboardMarkup {
+    let mut rows = vec![
+        vec![
+            path_button(state, tr!(lang, BtnGetLink), Action::GetLink, path),
+            path_button(state, tr!(lang, BtnSendToChat), Action::SendToChat, path),
+        ],
+        vec![
+            path_button(state, tr!(lang, BtnRename), Action::Rename, path),
+            path_button(state, tr!(lang, BtnMove), Action::Move, path),
+        ],
+        vec![
+            path_button(state, tr!(lang, BtnDelete), Action::Delete, path),
+            path_button(state, tr!(lang, BtnShare), Action::Share, path),
+        ],
+    ];
+    if is_archive(&uri::display_name(path)) {
+        rows.push(vec![path_button(
+            state,
+            tr!(lang, BtnExtract),
+            Action::Extract,
+            path,
+        )]);
+    }
+    InlineKeyboardMarkup::new(rows)
+}
+
+fn trash_item_keyboard(state: &StateStore, path: &str, lang: Lang) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![
+        path_button(state, tr!(lang, BtnRestore), Action::Restore, path),
+        path_button(state, tr!(lang, BtnPurge), Action::Purge, path),
+    ]])
+}
+
+// 回收站条目卡片，显示原位置和恢复/彻底删除按钮
+async fn send_trash_item(
+    bot: &Bot,
+    chat_id: ChatId,
+    client: &CloudreveClient,
+    state: &StateStore,
+    path: &str,
+    lang: Lang,
+) -> ResponseResult<()> {
+    let info = match client.get_file_info(path).await {
+        Ok(info) => info,
+        Err(e) => {
+            bot.send_message(chat_id, tr!(lang, GetFileInfoFailed, e))
+                .await?;
+            return Ok(());
+        }
+    };
+    let mut text = file_details_text(&info, lang);
+    let metadata = info.get("metadata");
+    if let Some(restore_uri) = metadata
+        .and_then(|m| m.get("sys:restore_uri"))
+        .and_then(|v| v.as_str())
+    {
+        text.push_str(&tr!(
+            lang,
+            RestoreLocation,
+            html::escape(&uri::decode(restore_uri))
+        ));
+    }
+    if let Some(collect_time) = metadata
+        .and_then(|m| m.get("sys:expected_collect_time"))
+        .and_then(|v| v.as_str())
+    {
+        text.push_str(&tr!(lang, AutoPurge, format_time(collect_time)));
+    }
+    bot.send_message(chat_id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(trash_item_keyboard(state, path, lang))
+        .await?;
+    Ok(())
+}
+
+// 发送文件详情卡片；图片和视频附带缩略图
+pub async fn send_file_details(
+    bot: &Bot,
+    chat_id: ChatId,
+    reply_to: MessageId,
+    client: &CloudreveClient,
+    state: &StateStore,
+    path: &str,
+    lang: Lang,
+) -> Result<()> {
+    let info = client.get_file_info(path).await?;
+    let text = file_details_text(&info, lang);
+    let keyboard = file_details_keyboard(state, path, lang);
+
+    let name = info
+        .get("name")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    let mime = guess_mime(name);
+    let thumbnail = if mime.starts_with("image/") || mime.starts_with("video/") {
+        match client.get_thumbnail(path).await {
+            Ok(url) => client.download_content(&url).await.ok(),
+            Err(e) => {
+                info!("No thumbnail for {}: {}", path, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    match thumbnail {
+        Some(content) => {
+            bot.send_photo(chat_id, InputFile::memory(content))
+                .caption(text)
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_parameters(ReplyParameters::new(reply_to))
+                .reply_markup(keyboard)
+                .await?;
+        }
+        None => {
+            bot.send_message(chat_id, text)
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_parameters(ReplyParameters::new(reply_to))
+                .reply_markup(keyboard)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn handle_pending_input(
+    bot: Bot,
+    msg: Message,
+    input: PendingInput,
+    client: Arc<CloudreveClient>,
+    lang: Lang,
+) -> ResponseResult<()> {
+    match input {
+        PendingInput::Rename { uri } => {
+            let new_name = msg.text().unwrap_or_default().trim();
+            let text = if new_name.is_empty() || new_name.contains('/') {
+                tr!(lang, InvalidFileName)
+            } else {
+                match client.rename_file(&uri, new_name).await {
+                    Ok(()) => tr!(lang, Renamed, new_name),
+                    Err(e) => tr!(lang, RenameFailed, e),
+                }
+            };
+            bot.send_message(msg.chat.id, text)
+                .reply_parameters(ReplyParameters::new(msg.id))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+// 内联查询最多返回的结果数，每个结果都要请求一次直链
+const INLINE_RESULT_LIMIT: u32 = 10;
+
+// ALLOWED_USERS: 逗号分隔的用户 id，未设置时任何人都不能使用内联查询
+fn is_allowed_user(user: UserId) -> bool {
+    env::var("ALLOWED_USERS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|id| id.trim().parse::<u64>().ok())
+        .any(|id| id == user.0)
+}
+
+// 结果 id 最长 64 字节，用路径的哈希
+fn inline_result_id(path: &str) -> String {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    path.hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+// Handler for inline queries (`@bot name` in any chat)
+pub async fn inline_query_handler(
+    bot: Bot,
+    q: InlineQuery,
+    client: Arc<CloudreveClient>,
+) -> ResponseResult<()> {
+    let query = q.query.trim();
+    if !is_allowed_user(q.from.id) || query.is_empty() {
+        bot.answer_inline_query(q.id, Vec::<InlineQueryResult>::new())
+            .is_personal(true)
+            .await?;
+        return Ok(());
+    }
+
+    let files = match client
+        .list_files()
+        .search(query)
+        .filter(FileFilter::Files)
+        .page_size(INLINE_RESULT_LIMIT)
+        .send()
+        .await
+    {
+        Ok((data, _)) => data
+            .get("files")
+            .and_then(|f| f.as_array())
+            .cloned()
+            .unwrap_or_default(),
+        Err(e) => {
+            error!("Inline search for {} failed: {}", query, e);
+            Vec::new()
+        }
+    };
+
+    let results: Vec<InlineQueryResult> = stream::iter(files)
+        .map(|file| {
+            let client = client.clone();
+            async move {
+                let name = file.get("name").and_then(|v| v.as_str())?.to_string();
+                let path = file.get("path").and_then(|v| v.as_str())?.to_string();
+                let size = file
+                    .get("size")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or_default();
+                let modified = file
+                    .get("updated_at")
+                    .and_then(|v| v.as_str())
+                    .map(format_time)
+                    .unwrap_or_default();
+                let link = match client
+                    .file_source(&path, DOWNLOAD_LINK)
+                    .await
+                    .map(|s| s.url)
+                {
+                    Ok(link) => link,
+                    Err(e) => {
+                        error!("Failed to get link for {}: {}", path, e);
+                        return None;
+                    }
+                };
+                let content = InputMessageContent::Text(InputMessageContentText::new(format!(
+                    "{} {}\n{}",
+                    file_icon(&name),
+                    name,
+                    link
+                )));
+                let id = inline_result_id(&path);
+                Some(InlineQueryResult::Article(
+                    InlineQueryResultArticle::new(
+                        id,
+                        format!("{} {}", file_icon(&name), name),
+                        content,
+                    )
+                    .description(format!(
+                        "{} · {} · {}",
+                        format_size(size),
+                        modified,
+                        uri::decode(&path)
+                    )),
+                ))
+            }
+        })
+        .buffered(RESOLVE_CONCURRENCY)
+        .filter_map(|result| async move { result })
+        .collect()
+        .await;
+
+    bot.answer_inline_query(q.id, results)
+        .is_personal(true)
+        .cache_time(10)
+        .await?;
+    Ok(())
+}
+
+// Handler for callback queries (button clicks)
+async fn answer_alert(bot: &Bot, query_id: String, text: String) {
+    bot.answer_callback_query(query_id)
+        .text(text)
+        .show_alert(true)
+        .send()
+        .await
+        .log_on_error()
+        .await;
+}
+
+pub async fn callback_handler(
+    bot: Bot,
+    q: CallbackQuery,
+    client: Arc<CloudreveClient>,
+    cache: PageTokenCache,
+    state: StateStore,
+) -> ResponseResult<()> {
+    let user_id = q.from.id;
+    let lang = state.lang(Some(&q.from)).await;
+    if let Some(data) = q.data {
+        let Some((action, payload)) = data.split_once(':') else {
+            return Ok(());
+        };
+        let Some(action) = Action::parse(action) else {
+            return Ok(());
+        };
+
+        // 回收站以及删除、移动、分享等会改动文件的按钮仅限管理员
+        if action.admin_only() && !is_admin(Some(&q.from)) {
+            answer_alert(&bot, q.id, tr!(lang, AdminOnly)).await;
+            return Ok(());
+        }
+
+        // 按钮中的路径以 path id 代替，放在参数最后；先还原，重启或过期后提示按钮失效
+        let (payload, path) = if action.needs_path() {
+            let (args, id) = payload.rsplit_once(':').unwrap_or(("", payload));
+            match state.path(id) {
+                Some(path) => (args, path),
+                None => {
+                    answer_alert(&bot, q.id, tr!(lang, ButtonExpired)).await;
+                    return Ok(());
+                }
+            }
+        } else {
+            (payload, String::new())
+        };
+        // 按钮会改动所在的消息，后台尚未完成的编辑不再适用
+        if let Some(msg) = &q.message {
+            state.bump_message_version(msg.chat().id, msg.id()).await;
+        }
+
+        bot.answer_callback_query(q.id)
+            .send()
+            .await
+            .log_on_error()
             .await;
 
         match action {
-            "cd" => {
-                let path = payload;
+            Action::Open => {
+                let path = path.as_str();
                 if let Some(msg) = q.message {
                     list_files_and_send(
                         &bot,
@@ -188,93 +1903,1485 @@ pub async fn callback_handler(
                         path,
                         0,
                         cache,
+                        &state,
                         Some(msg.id()),
+                        BrowseMode::Browse,
+                        lang,
                     )
                     .await?;
                 }
             }
-            "pg" => {
-                if let Some(last_colon) = payload.rfind(':') {
-                    let path = &payload[0..last_colon];
-                    if let Ok(page) = payload[last_colon + 1..].parse::<u32>() {
-                        if let Some(msg) = q.message {
-                            // Pass message ID for editing
-                            list_files_and_send(
-                                &bot,
-                                msg.chat().id,
-                                &client,
-                                path,
-                                page,
-                                cache,
-                                Some(msg.id()),
-                            )
-                            .await?;
+            // 翻页: pg:<page>:<path id>
+            Action::Page => {
+                if let (Ok(page), Some(msg)) = (payload.parse::<u32>(), q.message) {
+                    // Pass message ID for editing
+                    list_files_and_send(
+                        &bot,
+                        msg.chat().id,
+                        &client,
+                        &path,
+                        page,
+                        cache,
+                        &state,
+                        Some(msg.id()),
+                        BrowseMode::Browse,
+                        lang,
+                    )
+                    .await?;
+                }
+            }
+            // 缩略图预览: pv:<page>:<path id>
+            Action::Preview => {
+                if let (Ok(page), Some(msg)) = (payload.parse::<u32>(), q.message) {
+                    send_previews(
+                        &bot,
+                        msg.chat().id,
+                        &client,
+                        &path,
+                        page,
+                        cache,
+                        &state,
+                        lang,
+                    )
+                    .await?;
+                }
+            }
+            // 排序和筛选: so 排序字段, sd 排序方向, sf 筛选
+            Action::SortBy | Action::SortDirection | Action::Filter => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    let options = state.list_options(msg.chat().id).await;
+                    state
+                        .set_list_options(msg.chat().id, next_list_options(action, options))
+                        .await;
+                    list_files_and_send(
+                        &bot,
+                        msg.chat().id,
+                        &client,
+                        path,
+                        0,
+                        cache,
+                        &state,
+                        Some(msg.id()),
+                        BrowseMode::Browse,
+                        lang,
+                    )
+                    .await?;
+                }
+            }
+            // 获取直链: gl 发送新消息, gr 重新签发并原地更新
+            Action::GetLink | Action::RefreshLink => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    let options = SourceOptions {
+                        no_cache: action == Action::RefreshLink,
+                        ..DOWNLOAD_LINK
+                    };
+                    let (text, keyboard) = match client.file_source(path, options).await {
+                        Ok(source) => (
+                            link_text(&source, lang),
+                            Some(link_keyboard(&state, path, lang)),
+                        ),
+                        // 刷新失败时保留刷新按钮，便于重试
+                        Err(e) => (
+                            tr!(lang, GetLinkFailed, html::escape(&e.to_string())),
+                            (action == Action::RefreshLink)
+                                .then(|| link_keyboard(&state, path, lang)),
+                        ),
+                    };
+                    if action == Action::RefreshLink {
+                        let mut request = bot
+                            .edit_message_text(msg.chat().id, msg.id(), text)
+                            .parse_mode(teloxide::types::ParseMode::Html);
+                        if let Some(keyboard) = keyboard {
+                            request = request.reply_markup(keyboard);
                         }
+                        request.await?;
+                    } else {
+                        let mut request = bot
+                            .send_message(msg.chat().id, text)
+                            .parse_mode(teloxide::types::ParseMode::Html)
+                            .reply_parameters(ReplyParameters::new(msg.id()));
+                        if let Some(keyboard) = keyboard {
+                            request = request.reply_markup(keyboard);
+                        }
+                        request.await?;
                     }
                 }
             }
-            "gl" => {
-                let path = payload;
+            Action::Details => {
+                let path = path.as_str();
                 if let Some(msg) = q.message {
-                    let source = client.list_file_source(path).await;
-                    let url = match source {
-                        Ok(url) => url,
-                        Err(e) => {
-                            let text = format!("获取下载链接失败: {}", e);
-                            if let Some(mid) = Some(msg.id()) {
-                                bot.edit_message_text(msg.chat().id, mid, text).await?;
-                            } else {
-                                bot.send_message(msg.chat().id, text).await?;
-                            }
-                            return Ok(());
-                        }
-                    };
-                    let text = format!("单击下面的链接可直接复制：\n<code>{}</code>", url);
+                    if let Err(e) = send_file_details(
+                        &bot,
+                        msg.chat().id,
+                        msg.id(),
+                        &client,
+                        &state,
+                        path,
+                        lang,
+                    )
+                    .await
+                    {
+                        bot.send_message(msg.chat().id, tr!(lang, GetFileDetailsFailed, e))
+                            .reply_parameters(ReplyParameters::new(msg.id()))
+                            .await?;
+                    }
+                }
+            }
+            Action::Rename => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    let prompt = bot
+                        .send_message(
+                            msg.chat().id,
+                            tr!(lang, RenamePrompt, uri::display_name(path)),
+                        )
+                        .reply_parameters(ReplyParameters::new(msg.id()))
+                        .reply_markup(ForceReply::new())
+                        .await?;
+                    state
+                        .add_pending_input(
+                            prompt.chat.id,
+                            prompt.id,
+                            PendingInput::Rename {
+                                uri: path.to_string(),
+                            },
+                        )
+                        .await;
+                }
+            }
+            Action::Move => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    let id = state
+                        .add_pending(PendingAction::Move {
+                            uris: vec![path.to_string()],
+                            copy: false,
+                        })
+                        .await;
+                    let folder = uri::parent(path).unwrap_or_else(default_download_path);
+                    state.set_pending_browse_path(id, &folder).await;
+                    list_files_and_send(
+                        &bot,
+                        msg.chat().id,
+                        &client,
+                        &folder,
+                        0,
+                        cache,
+                        &state,
+                        None,
+                        BrowseMode::Pick(id),
+                        lang,
+                    )
+                    .await?;
+                }
+            }
+            // 删除: dl 请求确认, dc 确认删除, dx 取消
+            Action::Delete | Action::ConfirmDelete | Action::CancelDelete => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    match action {
+                        Action::Delete => {
+                            let keyboard = InlineKeyboardMarkup::new(vec![vec![
+                                path_button(
+                                    &state,
+                                    tr!(lang, BtnConfirmDelete),
+                                    Action::ConfirmDelete,
+                                    path,
+                                ),
+                                path_button(
+                                    &state,
+                                    tr!(lang, BtnCancel),
+                                    Action::CancelDelete,
+                                    path,
+                                ),
+                            ]]);
+                            bot.edit_message_reply_markup(msg.chat().id, msg.id())
+                                .reply_markup(keyboard)
+                                .await?;
+                        }
+                        Action::ConfirmDelete => {
+                            let text = match client.delete_files(&[path.to_string()]).await {
+                                Ok(()) => tr!(lang, Deleted, uri::display_name(path)),
+                                Err(e) => tr!(lang, DeleteFailed, e),
+                            };
+                            bot.edit_message_reply_markup(msg.chat().id, msg.id())
+                                .await?;
+                            bot.send_message(msg.chat().id, text)
+                                .reply_parameters(ReplyParameters::new(msg.id()))
+                                .await?;
+                        }
+                        _ => {
+                            bot.edit_message_reply_markup(msg.chat().id, msg.id())
+                                .reply_markup(file_details_keyboard(&state, path, lang))
+                                .await?;
+                        }
+                    }
+                }
+            }
+            // 选择模式: se 进入, st 切换, sg 翻页, sq 退出, sz 压缩
+            Action::Select => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    state.start_selection(msg.chat().id, msg.id(), path).await;
+                    list_files_and_send(
+                        &bot,
+                        msg.chat().id,
+                        &client,
+                        path,
+                        0,
+                        cache,
+                        &state,
+                        Some(msg.id()),
+                        BrowseMode::Select,
+                        lang,
+                    )
+                    .await?;
+                }
+            }
+            Action::SelectToggle | Action::SelectPage => {
+                let Some(msg) = q.message else {
+                    return Ok(());
+                };
+                let Some(selection) = state.selection(msg.chat().id, msg.id()).await else {
+                    return Ok(());
+                };
+                let (page, index) = payload.split_once(':').unwrap_or((payload, ""));
+                let Ok(page) = page.parse::<u32>() else {
+                    return Ok(());
+                };
+                if let Ok(index) = index.parse::<usize>() {
+                    state.toggle_selection(msg.chat().id, msg.id(), index).await;
+                }
+                list_files_and_send(
+                    &bot,
+                    msg.chat().id,
+                    &client,
+                    &selection.path,
+                    page,
+                    cache,
+                    &state,
+                    Some(msg.id()),
+                    BrowseMode::Select,
+                    lang,
+                )
+                .await?;
+            }
+            // 批量操作: sz 压缩, bd/bc/bx 删除, bm 移动, bp 复制, bs 分享, bl 链接, sq 退出
+            Action::SelectDone
+            | Action::SelectZip
+            | Action::SelectDelete
+            | Action::SelectConfirmDelete
+            | Action::SelectCancelDelete
+            | Action::SelectMove
+            | Action::SelectCopy
+            | Action::SelectShare
+            | Action::SelectLinks => {
+                let Some(msg) = q.message else {
+                    return Ok(());
+                };
+                let chat_id = msg.chat().id;
+                let Some(selection) = state.selection(chat_id, msg.id()).await else {
+                    return Ok(());
+                };
+                if action != Action::SelectDone && selection.selected.is_empty() {
+                    bot.send_message(chat_id, tr!(lang, SelectFilesFirst))
+                        .await?;
+                    return Ok(());
+                }
+                let selected = &selection.selected;
+                match action {
+                    Action::SelectZip => {
+                        let dst =
+                            uri::join(&selection.path, &archive_name(&selection.path, selected));
+                        let task = client.create_archive(selected, &dst).await;
+                        submit_workflow(
+                            &bot,
+                            chat_id,
+                            msg.id(),
+                            tr!(lang, WorkflowArchive),
+                            selection.path.clone(),
+                            client.clone(),
+                            &state,
+                            task,
+                            lang,
+                            state.chat_settings(chat_id).await.notify,
+                        )
+                        .await?;
+                    }
+                    Action::SelectDelete => {
+                        let keyboard = InlineKeyboardMarkup::new(vec![vec![
+                            InlineKeyboardButton::callback(
+                                tr!(lang, BtnConfirmDeleteCount, selected.len()),
+                                Action::SelectConfirmDelete.data(""),
+                            ),
+                            InlineKeyboardButton::callback(
+                                tr!(lang, BtnCancel),
+                                Action::SelectCancelDelete.data(""),
+                            ),
+                        ]]);
+                        bot.edit_message_reply_markup(chat_id, msg.id())
+                            .reply_markup(keyboard)
+                            .await?;
+                        return Ok(());
+                    }
+                    Action::SelectConfirmDelete => {
+                        let text = match client.delete_files(selected).await {
+                            Ok(()) => tr!(lang, DeletedCount, selected.len()),
+                            Err(e) => tr!(lang, DeleteFailed, e),
+                        };
+                        bot.send_message(chat_id, text)
+                            .reply_parameters(ReplyParameters::new(msg.id()))
+                            .await?;
+                    }
+                    Action::SelectMove | Action::SelectCopy => {
+                        let id = state
+                            .add_pending(PendingAction::Move {
+                                uris: selected.clone(),
+                                copy: action == Action::SelectCopy,
+                            })
+                            .await;
+                        state.set_pending_browse_path(id, &selection.path).await;
+                        list_files_and_send(
+                            &bot,
+                            chat_id,
+                            &client,
+                            &selection.path,
+                            0,
+                            cache.clone(),
+                            &state,
+                            None,
+                            BrowseMode::Pick(id),
+                            lang,
+                        )
+                        .await?;
+                    }
+                    Action::SelectShare => {
+                        send_share_links(&bot, chat_id, msg.id(), &client, selected, lang).await?;
+                        return Ok(());
+                    }
+                    Action::SelectLinks => {
+                        send_links_file(&bot, chat_id, msg.id(), &client, selected, lang).await?;
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+                // 取消删除时回到选择列表，其余操作结束选择模式
+                let mode = if action == Action::SelectCancelDelete {
+                    BrowseMode::Select
+                } else {
+                    state.take_selection(chat_id, msg.id()).await;
+                    BrowseMode::Browse
+                };
+                list_files_and_send(
+                    &bot,
+                    chat_id,
+                    &client,
+                    &selection.path,
+                    0,
+                    cache,
+                    &state,
+                    Some(msg.id()),
+                    mode,
+                    lang,
+                )
+                .await?;
+            }
+            Action::Extract => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    let dst = extract_destination(path);
+                    let task = client.extract_archive(path, &dst, None, None).await;
+                    submit_workflow(
+                        &bot,
+                        msg.chat().id,
+                        msg.id(),
+                        tr!(lang, WorkflowExtract),
+                        dst,
+                        client,
+                        &state,
+                        task,
+                        lang,
+                        state.chat_settings(msg.chat().id).await.notify,
+                    )
+                    .await?;
+                }
+            }
+            // 导出目录直链: xl 选择格式, xt 纯文本, xa aria2 输入文件
+            Action::ExportLinks => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    let keyboard = InlineKeyboardMarkup::new(vec![vec![
+                        path_button(&state, tr!(lang, BtnExportText), Action::ExportText, path),
+                        path_button(&state, tr!(lang, BtnExportAria2), Action::ExportAria2, path),
+                    ]]);
+                    bot.send_message(
+                        msg.chat().id,
+                        tr!(lang, ExportChooseFormat, uri::decode(path)),
+                    )
+                    .reply_parameters(ReplyParameters::new(msg.id()))
+                    .reply_markup(keyboard)
+                    .await?;
+                }
+            }
+            Action::ExportText | Action::ExportAria2 => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    export_folder_links(
+                        &bot,
+                        msg.chat().id,
+                        msg.id(),
+                        &client,
+                        path,
+                        action == Action::ExportAria2,
+                        lang,
+                    )
+                    .await?;
+                }
+            }
+            // 目录统计: fs 使用缓存, fr 重新统计
+            Action::Stats | Action::Recount => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    send_folder_stats(
+                        &bot,
+                        msg.chat().id,
+                        msg.id(),
+                        &client,
+                        &state,
+                        path,
+                        action == Action::Recount,
+                        lang,
+                    )
+                    .await?;
+                }
+            }
+            Action::TrashPage => {
+                if let (Ok(page), Some(msg)) = (payload.parse::<u32>(), q.message) {
+                    list_files_and_send(
+                        &bot,
+                        msg.chat().id,
+                        &client,
+                        uri::TRASH,
+                        page,
+                        cache,
+                        &state,
+                        Some(msg.id()),
+                        BrowseMode::Trash,
+                        lang,
+                    )
+                    .await?;
+                }
+            }
+            Action::TrashItem => {
+                if let Some(msg) = q.message {
+                    send_trash_item(&bot, msg.chat().id, &client, &state, &path, lang).await?;
+                }
+            }
+            Action::Restore => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    let text = match client.restore_files(&[path.to_string()]).await {
+                        Ok(()) => tr!(lang, Restored, uri::display_name(path)),
+                        Err(e) => tr!(lang, RestoreFailed, e),
+                    };
+                    bot.edit_message_reply_markup(msg.chat().id, msg.id())
+                        .await?;
+                    bot.send_message(msg.chat().id, text)
+                        .reply_parameters(ReplyParameters::new(msg.id()))
+                        .await?;
+                }
+            }
+            // 彻底删除: tp 询问, tq 确认, tk 取消
+            Action::Purge | Action::ConfirmPurge | Action::CancelPurge => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    match action {
+                        Action::Purge => {
+                            let keyboard = InlineKeyboardMarkup::new(vec![vec![
+                                path_button(
+                                    &state,
+                                    tr!(lang, BtnConfirmPurge),
+                                    Action::ConfirmPurge,
+                                    path,
+                                ),
+                                path_button(
+                                    &state,
+                                    tr!(lang, BtnCancel),
+                                    Action::CancelPurge,
+                                    path,
+                                ),
+                            ]]);
+                            bot.edit_message_reply_markup(msg.chat().id, msg.id())
+                                .reply_markup(keyboard)
+                                .await?;
+                        }
+                        Action::ConfirmPurge => {
+                            let text = match client.purge_files(&[path.to_string()]).await {
+                                Ok(()) => tr!(lang, Purged, uri::display_name(path)),
+                                Err(e) => tr!(lang, DeleteFailed, e),
+                            };
+                            bot.edit_message_reply_markup(msg.chat().id, msg.id())
+                                .await?;
+                            bot.send_message(msg.chat().id, text)
+                                .reply_parameters(ReplyParameters::new(msg.id()))
+                                .await?;
+                        }
+                        _ => {
+                            bot.edit_message_reply_markup(msg.chat().id, msg.id())
+                                .reply_markup(trash_item_keyboard(&state, path, lang))
+                                .await?;
+                        }
+                    }
+                }
+            }
+            // 清空回收站: te 询问, tc 确认
+            Action::EmptyTrash | Action::ConfirmEmptyTrash => {
+                if let Some(msg) = q.message {
+                    if action == Action::EmptyTrash {
+                        let keyboard = InlineKeyboardMarkup::new(vec![vec![
+                            InlineKeyboardButton::callback(
+                                tr!(lang, BtnConfirmEmptyTrash),
+                                Action::ConfirmEmptyTrash.data(""),
+                            ),
+                            InlineKeyboardButton::callback(
+                                tr!(lang, BtnCancel),
+                                Action::TrashPage.data(0),
+                            ),
+                        ]]);
+                        bot.edit_message_reply_markup(msg.chat().id, msg.id())
+                            .reply_markup(keyboard)
+                            .await?;
+                    } else {
+                        let text = match client.empty_trash().await {
+                            Ok(count) => tr!(lang, TrashEmptied, count),
+                            Err(e) => tr!(lang, EmptyTrashFailed, e),
+                        };
+                        bot.edit_message_text(msg.chat().id, msg.id(), text).await?;
+                    }
+                }
+            }
+            Action::Share => {
+                let path = path.as_str();
+                if let Some(msg) = q.message {
+                    let text = match client.create_share_link(path).await {
+                        Ok(link) => tr!(lang, ShareLink, html::escape(&link)),
+                        Err(e) => tr!(lang, CreateShareFailed, html::escape(&e.to_string())),
+                    };
+                    bot.send_message(msg.chat().id, text)
+                        .parse_mode(teloxide::types::ParseMode::Html)
+                        .reply_parameters(ReplyParameters::new(msg.id()))
+                        .await?;
+                }
+            }
+            Action::SendToChat => {
+                if let Some(msg) = q.message {
+                    // 下载可能需要较长时间，放到后台以免阻塞该聊天的其他更新
+                    let (bot, client) = (bot.clone(), client.clone());
+                    let (chat_id, message_id) = (msg.chat().id, msg.id());
+                    tokio::spawn(async move {
+                        if let Err(e) = send_file_to_chat(&bot, chat_id, &client, &path, lang).await
+                        {
+                            let _ = bot
+                                .send_message(chat_id, tr!(lang, SendFileFailed, e))
+                                .reply_parameters(ReplyParameters::new(message_id))
+                                .await;
+                        }
+                    });
+                }
+            }
+            Action::SubmitDownload => {
+                let pending = match payload.parse::<u64>() {
+                    Ok(id) => state.take_pending(id).await,
+                    Err(_) => None,
+                };
+                if let Some(MaybeInaccessibleMessage::Regular(message)) = q.message {
+                    match pending {
+                        Some(pending) => {
+                            let _ = submit_pending(
+                                bot.clone(),
+                                message.clone(),
+                                pending,
+                                user_id,
+                                client.clone(),
+                                &state,
+                                lang,
+                            )
+                            .await;
+                        }
+                        None => {
+                            bot.edit_message_text(
+                                message.chat.id,
+                                message.id,
+                                tr!(lang, DownloadExpired),
+                            )
+                            .await?;
+                        }
+                    }
+                }
+            }
+            // 选择保存目录: pk 打开, pc 进入子目录, pp 翻页, ps 保存, pr 最近使用, px 取消
+            Action::PickOpen
+            | Action::PickFolder
+            | Action::PickPage
+            | Action::PickSave
+            | Action::PickRecent
+            | Action::PickCancel => {
+                let (id, rest) = payload.split_once(':').unwrap_or((payload, ""));
+                let Ok(id) = id.parse::<u64>() else {
+                    return Ok(());
+                };
+                let Some(msg) = q.message else {
+                    return Ok(());
+                };
+                let Some(pending) = state.pending(id).await else {
+                    bot.edit_message_text(msg.chat().id, msg.id(), tr!(lang, TaskExpired))
+                        .await?;
+                    return Ok(());
+                };
+                let settings = state.chat_settings(msg.chat().id).await;
+                let current_path = pending
+                    .browse_path
+                    .clone()
+                    .unwrap_or_else(|| download_path(&settings));
+                match action {
+                    Action::PickOpen | Action::PickFolder | Action::PickPage => {
+                        let (path, page) = match action {
+                            Action::PickFolder => (path.clone(), 0),
+                            Action::PickPage => (current_path, rest.parse::<u32>().unwrap_or(0)),
+                            _ => (current_path, 0),
+                        };
+                        state.set_pending_browse_path(id, &path).await;
+                        list_files_and_send(
+                            &bot,
+                            msg.chat().id,
+                            &client,
+                            &path,
+                            page,
+                            cache,
+                            &state,
+                            Some(msg.id()),
+                            BrowseMode::Pick(id),
+                            lang,
+                        )
+                        .await?;
+                    }
+                    Action::PickSave | Action::PickRecent => {
+                        let dst = if action == Action::PickRecent {
+                            path.clone()
+                        } else {
+                            current_path
+                        };
+                        state.set_pending_dst(id, &dst).await;
+                        if let (Some(pending), MaybeInaccessibleMessage::Regular(message)) =
+                            (state.take_pending(id).await, msg)
+                        {
+                            let _ = submit_pending(
+                                bot.clone(),
+                                message,
+                                pending,
+                                user_id,
+                                client.clone(),
+                                &state,
+                                lang,
+                            )
+                            .await;
+                        }
+                    }
+                    _ => {
+                        bot.edit_message_text(msg.chat().id, msg.id(), tr!(lang, Canceled))
+                            .await?;
+                    }
+                }
+            }
+            // 设置菜单: cs:<key>，修改后原地更新菜单
+            Action::Settings => {
+                let Some(msg) = q.message else {
+                    return Ok(());
+                };
+                let chat_id = msg.chat().id;
+                let mut settings = state.chat_settings(chat_id).await;
+                let mut options = state.list_options(chat_id).await;
+                let mut lang = lang;
+                match payload {
+                    "page" => {
+                        settings.page_size = PAGE_SIZES
+                            .iter()
+                            .copied()
+                            .find(|size| *size > settings.page_size)
+                            .unwrap_or(PAGE_SIZES[0]);
+                    }
+                    "sort" | "dir" => {
+                        let action = if payload == "sort" {
+                            Action::SortBy
+                        } else {
+                            Action::SortDirection
+                        };
+                        options = next_list_options(action, options);
+                        state.set_list_options(chat_id, options).await;
+                    }
+                    // 语言按用户保存，不属于聊天设置
+                    "lang" => {
+                        lang = match lang {
+                            Lang::Zh => Lang::En,
+                            Lang::En => Lang::Zh,
+                        };
+                        state.set_lang(user_id, lang).await;
+                    }
+                    "notify" => settings.notify = !settings.notify,
+                    "offer" => settings.auto_offer = !settings.auto_offer,
+                    // 在目录选择器中选择，保存后由 submit_pending 写入设置
+                    "root" | "dst" => {
+                        let (action, folder) = if payload == "root" {
+                            (PendingAction::SetRoot, browse_root(&settings))
+                        } else {
+                            (
+                                PendingAction::SetDownloadDestination,
+                                download_path(&settings),
+                            )
+                        };
+                        let id = state.add_pending(action).await;
+                        state.set_pending_browse_path(id, &folder).await;
+                        list_files_and_send(
+                            &bot,
+                            chat_id,
+                            &client,
+                            &folder,
+                            0,
+                            cache,
+                            &state,
+                            None,
+                            BrowseMode::Pick(id),
+                            lang,
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+                    "reset" => {
+                        settings = ChatSettings::default();
+                        options = ListOptions::default();
+                        state.set_list_options(chat_id, options).await;
+                    }
+                    _ => return Ok(()),
+                }
+                state.set_chat_settings(chat_id, settings.clone()).await;
+                bot.edit_message_text(chat_id, msg.id(), settings_text(&settings, lang))
+                    .parse_mode(teloxide::types::ParseMode::Html)
+                    .reply_markup(settings_keyboard(&settings, &options, lang))
+                    .await?;
+            }
+            // 切换语言: lg:en, lg:zh
+            Action::Language => {
+                if let (Some(new_lang), Some(msg)) = (Lang::parse(payload), q.message) {
+                    state.set_lang(user_id, new_lang).await;
+                    bot.edit_message_text(msg.chat().id, msg.id(), tr!(new_lang, LangSet))
+                        .await?;
+                }
+            }
+            Action::Noop => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn order_by_label(order_by: OrderBy, lang: Lang) -> String {
+    match order_by {
+        OrderBy::Name => tr!(lang, OrderName),
+        OrderBy::Size => tr!(lang, OrderSize),
+        OrderBy::Created => tr!(lang, OrderCreated),
+        OrderBy::Modified => tr!(lang, OrderModified),
+    }
+}
+
+fn filter_label(filter: FileFilter, lang: Lang) -> String {
+    match filter {
+        FileFilter::All => tr!(lang, FilterAll),
+        FileFilter::Folders => tr!(lang, FilterFolders),
+        FileFilter::Files => tr!(lang, FilterFiles),
+        FileFilter::Images => tr!(lang, FilterImages),
+        FileFilter::Videos => tr!(lang, FilterVideos),
+        FileFilter::Audio => tr!(lang, FilterAudio),
+        FileFilter::Documents => tr!(lang, FilterDocuments),
+    }
+}
+
+fn direction_label(direction: OrderDirection, lang: Lang) -> String {
+    match direction {
+        OrderDirection::Asc => tr!(lang, SortAsc),
+        OrderDirection::Desc => tr!(lang, SortDesc),
+    }
+}
+
+// 排序和筛选切换按钮，每次点击切换到下一个选项
+fn options_row(
+    state: &StateStore,
+    path: &str,
+    options: &ListOptions,
+    lang: Lang,
+) -> Vec<InlineKeyboardButton> {
+    let direction = direction_label(options.order_direction, lang);
+    vec![
+        path_button(
+            state,
+            tr!(lang, SortButton, order_by_label(options.order_by, lang)),
+            Action::SortBy,
+            path,
+        ),
+        path_button(state, direction, Action::SortDirection, path),
+        path_button(
+            state,
+            tr!(lang, FilterButton, filter_label(options.filter, lang)),
+            Action::Filter,
+            path,
+        ),
+    ]
+}
+
+fn next_list_options(action: Action, options: ListOptions) -> ListOptions {
+    let mut options = options;
+    match action {
+        Action::SortBy => {
+            options.order_by = match options.order_by {
+                OrderBy::Name => OrderBy::Size,
+                OrderBy::Size => OrderBy::Created,
+                OrderBy::Created => OrderBy::Modified,
+                OrderBy::Modified => OrderBy::Name,
+            }
+        }
+        Action::SortDirection => {
+            options.order_direction = match options.order_direction {
+                OrderDirection::Asc => OrderDirection::Desc,
+                OrderDirection::Desc => OrderDirection::Asc,
+            }
+        }
+        _ => {
+            options.filter = match options.filter {
+                FileFilter::All => FileFilter::Folders,
+                FileFilter::Folders => FileFilter::Files,
+                FileFilter::Files => FileFilter::Images,
+                FileFilter::Images => FileFilter::Videos,
+                FileFilter::Videos => FileFilter::Audio,
+                FileFilter::Audio => FileFilter::Documents,
+                FileFilter::Documents => FileFilter::All,
+            }
+        }
+    }
+    options
+}
+
+// 面包屑最多显示的层级数，更上层折叠为 "…"
+const MAX_BREADCRUMBS: usize = 4;
+
+// 列表顶部的导航：上一级、根目录，以及可点击的路径
+fn navigation_rows(
+    state: &StateStore,
+    path: &str,
+    mode: BrowseMode,
+    lang: Lang,
+) -> Vec<Vec<InlineKeyboardButton>> {
+    // 选择模式下只能在当前目录内选择
+    if matches!(mode, BrowseMode::Select) {
+        return Vec::new();
+    }
+    let Some(parent) = uri::parent(path) else {
+        return Vec::new();
+    };
+    let header = vec![
+        InlineKeyboardButton::callback(tr!(lang, BtnParent), mode.cd_data(state, &parent)),
+        InlineKeyboardButton::callback(tr!(lang, BtnRoot), mode.cd_data(state, uri::root(path))),
+    ];
+
+    let crumbs = uri::breadcrumbs(path);
+    let skip = crumbs.len().saturating_sub(MAX_BREADCRUMBS);
+    let mut breadcrumb_row = Vec::new();
+    if skip > 0 {
+        breadcrumb_row.push(InlineKeyboardButton::callback(
+            "…",
+            mode.cd_data(state, &crumbs[skip - 1].1),
+        ));
+    }
+    for (label, target) in &crumbs[skip..] {
+        breadcrumb_row.push(InlineKeyboardButton::callback(
+            label.clone(),
+            mode.cd_data(state, target),
+        ));
+    }
+    vec![header, breadcrumb_row]
+}
+
+// 遍历目录获取所有文件直链，发送 txt 或 aria2 输入文件
+async fn export_folder_links(
+    bot: &Bot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    client: &CloudreveClient,
+    folder: &str,
+    aria2: bool,
+    lang: Lang,
+) -> ResponseResult<()> {
+    bot.edit_message_text(chat_id, message_id, tr!(lang, ExportWalking))
+        .await?;
+    let files: Result<Vec<FileEntry>> = client
+        .walk(folder, usize::MAX)
+        .try_filter(|entry| future::ready(!entry.is_folder()))
+        .try_collect()
+        .await;
+    let files = match files {
+        Ok(files) => files,
+        Err(e) => {
+            bot.edit_message_text(chat_id, message_id, tr!(lang, ExportWalkFailed, e))
+                .await?;
+            return Ok(());
+        }
+    };
+    let total_size: u64 = files.iter().map(|f| f.size).sum();
+    let uris: Vec<String> = files.into_iter().map(|f| f.path).collect();
+    if uris.is_empty() {
+        bot.edit_message_text(chat_id, message_id, tr!(lang, ExportNoFiles))
+            .await?;
+        return Ok(());
+    }
+    bot.edit_message_text(chat_id, message_id, tr!(lang, ExportFetching, uris.len()))
+        .await?;
+    let sources = match client.list_file_source(&uris, DOWNLOAD_LINK).await {
+        Ok(sources) => sources,
+        Err(e) => {
+            bot.edit_message_text(chat_id, message_id, tr!(lang, GetLinksFailed, e))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let prefix = format!("{}/", folder.trim_end_matches('/'));
+    let mut content = String::new();
+    for (path, source) in uris.iter().filter_map(|u| Some((u, sources.get(u)?))) {
+        content.push_str(&source.url);
+        content.push('\n');
+        if aria2 {
+            // aria2 按目录结构保存: out 为相对导出目录的路径
+            let relative = path.strip_prefix(&prefix).unwrap_or(path);
+            content.push_str(&format!("  out={}\n", uri::decode(relative)));
+        }
+    }
+    let file_name = format!(
+        "{}{}.txt",
+        uri::display_name(folder).replace("://", "_"),
+        if aria2 { "-aria2" } else { "" }
+    );
+    let mut caption = tr!(lang, ExportSummary, sources.len(), format_size(total_size));
+    if sources.len() < uris.len() {
+        caption.push_str(&tr!(lang, ExportFailedCount, uris.len() - sources.len()));
+    }
+    if let Some(expires) = sources.values().find_map(|s| s.expires.as_deref()) {
+        caption.push_str(&tr!(lang, LinksExpireAt, format_time(expires)));
+    }
+    bot.edit_message_text(
+        chat_id,
+        message_id,
+        tr!(lang, Exported, uri::decode(folder)),
+    )
+    .await?;
+    bot.send_document(
+        chat_id,
+        InputFile::memory(content.into_bytes()).file_name(file_name),
+    )
+    .caption(caption)
+    .reply_parameters(ReplyParameters::new(message_id))
+    .await?;
+    Ok(())
+}
+
+// 发给用户的直链都以下载方式打开
+const DOWNLOAD_LINK: SourceOptions = SourceOptions {
+    download: true,
+    archive: false,
+    no_cache: false,
+};
+
+fn link_text(source: &FileSource, lang: Lang) -> String {
+    let mut text = tr!(lang, LinkText, html::escape(&source.url));
+    if let Some(expires) = &source.expires {
+        text.push_str(&tr!(lang, LinkExpiresAt, format_time(expires)));
+    }
+    text
+}
+
+fn link_keyboard(state: &StateStore, path: &str, lang: Lang) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![path_button(
+        state,
+        tr!(lang, BtnRefreshLink),
+        Action::RefreshLink,
+        path,
+    )]])
+}
+
+// 列表标题中的合计按目录和筛选条件缓存
+fn folder_totals_key(path: &str, filter: FileFilter) -> String {
+    format!("{}|{:?}", path, filter)
+}
+
+// 目录中符合筛选条件的条目合计，用于列表标题
+async fn folder_totals(
+    client: &CloudreveClient,
+    state: &StateStore,
+    path: &str,
+    filter: FileFilter,
+) -> Result<FolderStats> {
+    let key = folder_totals_key(path, filter);
+    if let Some(totals) = state.folder_totals(&key).await {
+        return Ok(totals);
+    }
+    let mut totals = FolderStats::default();
+    let mut entries = std::pin::pin!(client.list_all_filtered(path, filter));
+    while let Some(entry) = entries.try_next().await? {
+        if entry.is_folder() {
+            totals.folders += 1;
+        } else {
+            totals.files += 1;
+            totals.total_size += entry.size;
+        }
+    }
+    state.set_folder_totals(&key, totals.clone()).await;
+    Ok(totals)
+}
+
+fn folder_totals_text(totals: &FolderStats, lang: Lang) -> String {
+    tr!(
+        lang,
+        FolderTotals,
+        totals.folders,
+        totals.files,
+        format_size(totals.total_size)
+    )
+}
+
+// 大目录统计较慢，先显示列表，统计完成后再补上标题中的合计；
+// 期间消息被其他按钮改动过则放弃，以免覆盖新的内容
+#[allow(clippy::too_many_arguments)]
+async fn spawn_folder_totals(
+    bot: Bot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    client: Arc<CloudreveClient>,
+    state: StateStore,
+    path: String,
+    filter: FileFilter,
+    text: String,
+    keyboard: InlineKeyboardMarkup,
+    lang: Lang,
+) {
+    let version = state.message_version(chat_id, message_id).await;
+    tokio::spawn(async move {
+        let totals = match folder_totals(&client, &state, &path, filter).await {
+            Ok(totals) => totals,
+            Err(e) => {
+                error!("Failed to count {}: {}", path, e);
+                return;
+            }
+        };
+        if state.message_version(chat_id, message_id).await != version {
+            return;
+        }
+        let text = text + &folder_totals_text(&totals, lang);
+        if let Err(e) = bot
+            .edit_message_text(chat_id, message_id, text)
+            .reply_markup(keyboard)
+            .await
+        {
+            error!("Failed to show the totals of {}: {}", path, e);
+        }
+    });
+}
+
+// 统计时每隔多久更新一次进度消息
+const STATS_PROGRESS_INTERVAL: Duration = Duration::from_secs(3);
+// 统计结果中最多列出的读取失败的目录数
+const MAX_FAILED_FOLDERS_SHOWN: usize = 5;
+
+fn folder_stats_text(path: &str, stats: &FolderStats, lang: Lang) -> String {
+    let mut text = tr!(
+        lang,
+        Stats,
+        html::escape(&uri::decode(path)),
+        stats.files,
+        stats.folders,
+        format_size(stats.total_size)
+    );
+    let mut by_type: Vec<_> = stats.by_type.iter().collect();
+    // 按大小从大到小
+    by_type.sort_by_key(|(_, (_, size))| std::cmp::Reverse(*size));
+    if !by_type.is_empty() {
+        text.push('\n');
+    }
+    for (icon, (count, size)) in by_type {
+        text.push_str(&tr!(lang, StatsByType, icon, count, format_size(*size)));
+    }
+    if !stats.failed.is_empty() {
+        let failed: Vec<String> = stats
+            .failed
+            .iter()
+            .take(MAX_FAILED_FOLDERS_SHOWN)
+            .map(|e| format!("<code>{}</code>", html::escape(&uri::decode(e))))
+            .collect();
+        text.push_str(&tr!(
+            lang,
+            StatsIncomplete,
+            stats.failed.len(),
+            failed.join("\n")
+        ));
+    }
+    text
+}
+
+// 遍历目录统计大小和文件数，结果缓存在 StateStore 中
+#[allow(clippy::too_many_arguments)]
+async fn send_folder_stats(
+    bot: &Bot,
+    chat_id: ChatId,
+    reply_to: MessageId,
+    client: &CloudreveClient,
+    state: &StateStore,
+    path: &str,
+    refresh: bool,
+    lang: Lang,
+) -> ResponseResult<()> {
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![path_button(
+        state,
+        tr!(lang, BtnRecount),
+        Action::Recount,
+        path,
+    )]]);
+    let cached = if refresh {
+        None
+    } else {
+        state.folder_stats(path).await
+    };
+    if let Some(stats) = cached {
+        bot.send_message(chat_id, folder_stats_text(path, &stats, lang))
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_parameters(ReplyParameters::new(reply_to))
+            .reply_markup(keyboard)
+            .await?;
+        return Ok(());
+    }
+
+    let progress = bot
+        .send_message(chat_id, tr!(lang, StatsCounting))
+        .reply_parameters(ReplyParameters::new(reply_to))
+        .await?;
+    let mut stats = FolderStats::default();
+    let mut last_update = Instant::now();
+    let mut entries = std::pin::pin!(client.walk(path, usize::MAX));
+    while let Some(entry) = entries.next().await {
+        // 读取失败的目录记录下来，继续统计其余目录
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                stats.failed.push(e.to_string());
+                continue;
+            }
+        };
+        if entry.is_folder() {
+            stats.folders += 1;
+        } else {
+            stats.files += 1;
+            stats.total_size += entry.size;
+            let by_type = stats.by_type.entry(file_icon(&entry.name)).or_default();
+            by_type.0 += 1;
+            by_type.1 += entry.size;
+        }
+        // 大目录需要较长时间，定期显示进度
+        if last_update.elapsed() >= STATS_PROGRESS_INTERVAL {
+            last_update = Instant::now();
+            bot.edit_message_text(
+                chat_id,
+                progress.id,
+                tr!(
+                    lang,
+                    StatsProgress,
+                    stats.files,
+                    stats.folders,
+                    format_size(stats.total_size)
+                ),
+            )
+            .await?;
+        }
+    }
+
+    bot.edit_message_text(chat_id, progress.id, folder_stats_text(path, &stats, lang))
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(keyboard)
+        .await?;
+    // 不完整的结果不缓存，下次重新统计
+    if stats.failed.is_empty() {
+        state.set_folder_stats(path, stats).await;
+    }
+    Ok(())
+}
+
+// 选择模式下对已选文件的操作
+fn selection_bar(lang: Lang) -> Vec<Vec<InlineKeyboardButton>> {
+    vec![
+        vec![
+            InlineKeyboardButton::callback(tr!(lang, BtnZip), Action::SelectZip.data("")),
+            InlineKeyboardButton::callback(tr!(lang, BtnDelete), Action::SelectDelete.data("")),
+        ],
+        vec![
+            InlineKeyboardButton::callback(tr!(lang, BtnMove), Action::SelectMove.data("")),
+            InlineKeyboardButton::callback(tr!(lang, BtnCopy), Action::SelectCopy.data("")),
+        ],
+        vec![
+            InlineKeyboardButton::callback(tr!(lang, BtnShare), Action::SelectShare.data("")),
+            InlineKeyboardButton::callback(tr!(lang, BtnLinksFile), Action::SelectLinks.data("")),
+        ],
+        vec![InlineKeyboardButton::callback(
+            tr!(lang, BtnDone),
+            Action::SelectDone.data(""),
+        )],
+    ]
+}
+
+// 为每个已选文件创建分享链接，合并为一条消息
+async fn send_share_links(
+    bot: &Bot,
+    chat_id: ChatId,
+    reply_to: MessageId,
+    client: &CloudreveClient,
+    uris: &[String],
+    lang: Lang,
+) -> ResponseResult<()> {
+    let lines: Vec<String> = stream::iter(uris.to_vec())
+        .map(|path| async move {
+            let name = html::escape(&uri::display_name(&path));
+            match client.create_share_link(&path).await {
+                Ok(link) => format!("<b>{}</b>\n<code>{}</code>", name, html::escape(&link)),
+                Err(e) => format!(
+                    "<b>{}</b>\n{}",
+                    name,
+                    tr!(lang, CreateShareFailed, html::escape(&e.to_string()))
+                ),
+            }
+        })
+        .buffered(RESOLVE_CONCURRENCY)
+        .collect()
+        .await;
+    bot.send_message(chat_id, lines.join("\n\n"))
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_parameters(ReplyParameters::new(reply_to))
+        .await?;
+    Ok(())
+}
+
+// 获取已选文件的直链，作为 txt 文件发送，每行一个
+async fn send_links_file(
+    bot: &Bot,
+    chat_id: ChatId,
+    reply_to: MessageId,
+    client: &CloudreveClient,
+    uris: &[String],
+    lang: Lang,
+) -> ResponseResult<()> {
+    let sources = match client.list_file_source(uris, DOWNLOAD_LINK).await {
+        Ok(sources) => sources,
+        Err(e) => {
+            bot.send_message(chat_id, tr!(lang, GetLinksFailed, e))
+                .reply_parameters(ReplyParameters::new(reply_to))
+                .await?;
+            return Ok(());
+        }
+    };
+    let mut links = Vec::new();
+    let mut failed = Vec::new();
+    for path in uris {
+        match sources.get(path) {
+            Some(source) => links.push(source.url.clone()),
+            None => failed.push(uri::display_name(path)),
+        }
+    }
+    if links.is_empty() {
+        bot.send_message(chat_id, tr!(lang, NoLinkableFiles))
+            .reply_parameters(ReplyParameters::new(reply_to))
+            .await?;
+        return Ok(());
+    }
+    let mut caption = tr!(lang, LinksSummary, links.len());
+    if !failed.is_empty() {
+        caption.push_str(&tr!(lang, LinksFailedFiles, failed.join(", ")));
+    }
+    if let Some(expires) = sources.values().find_map(|s| s.expires.as_deref()) {
+        caption.push_str(&tr!(lang, LinksExpireAt, format_time(expires)));
+    }
+    let content = links.join("\n") + "\n";
+    bot.send_document(
+        chat_id,
+        InputFile::memory(content.into_bytes()).file_name("links.txt"),
+    )
+    .caption(caption)
+    .reply_parameters(ReplyParameters::new(reply_to))
+    .await?;
+    Ok(())
+}
+
+// 单个文件以其名称命名压缩包，多个文件以所在目录命名
+fn archive_name(folder: &str, selected: &[String]) -> String {
+    let base = match selected {
+        [single] => uri::display_name(single),
+        _ if uri::parent(folder).is_some() => uri::display_name(folder),
+        _ => "archive".to_string(),
+    };
+    format!("{}.zip", base)
+}
+
+fn is_archive(name: &str) -> bool {
+    matches!(
+        guess_mime(name),
+        "application/zip"
+            | "application/vnd.rar"
+            | "application/x-7z-compressed"
+            | "application/x-tar"
+            | "application/gzip"
+    )
+}
+
+// 解压到压缩包所在目录下的同名文件夹: a/b.tar.gz -> a/b
+fn extract_destination(path: &str) -> String {
+    let name = uri::display_name(path);
+    let stem = name
+        .rsplit_once('.')
+        .map_or(name.as_str(), |(stem, _)| stem);
+    let stem = stem.strip_suffix(".tar").unwrap_or(stem);
+    let folder = uri::parent(path).unwrap_or_else(|| uri::root(path).to_string());
+    uri::join(&folder, stem)
+}
+
+// 排序、筛选或每页条目数不同时分页 token 也不同
+fn page_cache_path(path: &str, options: &ListOptions, page_size: u32) -> String {
+    format!(
+        "{}|{}|{}|{:?}|{}",
+        path,
+        options.order_by.as_str(),
+        options.order_direction.as_str(),
+        options.filter,
+        page_size
+    )
+}
+
+async fn page_token(cache: &PageTokenCache, cache_path: &str, page: u32) -> String {
+    if page == 0 {
+        return String::new();
+    }
+    let key = format!("{}:{}", cache_path, page);
+    let cache_lock = cache.lock().await;
+    cache_lock.get(&key).cloned().unwrap_or_default()
+}
+
+fn is_media(name: &str) -> bool {
+    let mime = guess_mime(name);
+    mime.starts_with("image/") || mime.starts_with("video/")
+}
+
+// 发送当前页图片和视频的缩略图，每组最多 10 张
+#[allow(clippy::too_many_arguments)]
+pub async fn send_previews(
+    bot: &Bot,
+    chat_id: ChatId,
+    client: &CloudreveClient,
+    path: &str,
+    page: u32,
+    cache: PageTokenCache,
+    state: &StateStore,
+    lang: Lang,
+) -> ResponseResult<()> {
+    let options = state.list_options(chat_id).await;
+    let page_size = state.chat_settings(chat_id).await.page_size;
+    let token = page_token(&cache, &page_cache_path(path, &options, page_size), page).await;
+    let files = match client
+        .list_files()
+        .uri(path)
+        .page(page)
+        .page_size(page_size)
+        .next_page_token(&token)
+        .order_by(options.order_by)
+        .order_direction(options.order_direction)
+        .filter(options.filter)
+        .send()
+        .await
+    {
+        Ok((data, _)) => data
+            .get("files")
+            .and_then(|f| f.as_array())
+            .cloned()
+            .unwrap_or_default(),
+        Err(e) => {
+            bot.send_message(chat_id, tr!(lang, ListFailed, e)).await?;
+            return Ok(());
+        }
+    };
 
-                    if let Some(mid) = Some(msg.id()) {
-                        bot.edit_message_text(msg.chat().id, mid, text)
-                            .parse_mode(teloxide::types::ParseMode::Html)
-                            .await?;
-                    } else {
-                        bot.send_message(msg.chat().id, text)
-                            .parse_mode(teloxide::types::ParseMode::Html)
-                            .await?;
-                    }
-                }
-            }
-            "rd" => {
-                let path = payload;
-                if let Some(MaybeInaccessibleMessage::Regular(message)) = q.message {
-                    let _ =
-                        send_remote_download(bot.clone(), message.clone(), path, client.clone())
-                            .await;
-                }
+    let media: Vec<InputMedia> = stream::iter(files)
+        .filter_map(|file| async move {
+            let name = file.get("name").and_then(|v| v.as_str())?.to_string();
+            let path = file.get("path").and_then(|v| v.as_str())?.to_string();
+            (file.get("type").and_then(|v| v.as_i64()) == Some(0) && is_media(&name))
+                .then_some((name, path))
+        })
+        .map(|(name, path)| async move {
+            let url = client
+                .get_thumbnail(&path)
+                .await
+                .map_err(|e| info!("No thumbnail for {}: {}", path, e))
+                .ok()?;
+            let content = client.download_content(&url).await.ok()?;
+            Some(InputMedia::Photo(
+                InputMediaPhoto::new(InputFile::memory(content)).caption(name),
+            ))
+        })
+        .buffered(RESOLVE_CONCURRENCY)
+        .filter_map(|media| async move { media })
+        .collect()
+        .await;
+
+    if media.is_empty() {
+        bot.send_message(chat_id, tr!(lang, NoThumbnails)).await?;
+        return Ok(());
+    }
+    for group in media.chunks(10) {
+        if let [InputMedia::Photo(photo)] = group {
+            let mut request = bot.send_photo(chat_id, photo.media.clone());
+            if let Some(caption) = &photo.caption {
+                request = request.caption(caption.clone());
             }
-            _ => {}
+            request.await?;
+        } else {
+            bot.send_media_group(chat_id, group.to_vec()).await?;
         }
     }
-
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn list_files_and_send(
     bot: &Bot,
     chat_id: ChatId,
-    client: &CloudreveClient,
+    client: &Arc<CloudreveClient>,
     path: &str,
     page: u32,
     cache: PageTokenCache,
+    state: &StateStore,
     message_id: Option<MessageId>,
+    mode: BrowseMode,
+    lang: Lang,
 ) -> ResponseResult<()> {
-    let page_size = 10;
-
-    let token_to_use = if page > 0 {
-        let key = format!("{}:{}", path, page);
-        let cache_lock = cache.lock().await;
-        cache_lock.get(&key).cloned().unwrap_or_default()
-    } else {
-        String::new()
+    let page_size = state.chat_settings(chat_id).await.page_size;
+    // 回收站和目录选择器不使用文件列表的排序和筛选，否则筛选会隐藏所有目录
+    let options = match mode {
+        BrowseMode::Trash | BrowseMode::Pick(_) => ListOptions::default(),
+        BrowseMode::Browse | BrowseMode::Select => state.list_options(chat_id).await,
     };
+    let cache_path = page_cache_path(path, &options, page_size);
+    let token_to_use = page_token(&cache, &cache_path, page).await;
 
     match client
         .list_files()
@@ -282,6 +3389,9 @@ pub async fn list_files_and_send(
         .page(page)
         .page_size(page_size)
         .next_page_token(&token_to_use)
+        .order_by(options.order_by)
+        .order_direction(options.order_direction)
+        .filter(options.filter)
         .send()
         .await
     {
@@ -292,72 +3402,121 @@ pub async fn list_files_and_send(
             } else {
                 data.as_array()
             };
-            let pagination = data.get("pagination").and_then(|v| v.as_object());
-            if let Some(next_token) = pagination
-                .and_then(|v| v.get("next_token"))
-                .and_then(|v| v.as_str())
-            {
-                if next_token.is_empty() {
-                } else {
-                    let key = format!("{}:{}", path, page + 1);
-                    let mut cache_lock = cache.lock().await;
-                    cache_lock.insert(key, next_token.to_string());
-                }
+            if let Some(next_token) = &next_token {
+                let key = format!("{}:{}", cache_path, page + 1);
+                let mut cache_lock = cache.lock().await;
+                cache_lock.insert(key, next_token.clone());
             }
             if let Some(files) = files_opt {
-                if files.is_empty() && page == 0 {
-                    let text = format!("Directory `{}` is empty.", path);
+                if files.is_empty() && page == 0 && !matches!(mode, BrowseMode::Pick(_)) {
+                    let text = match mode {
+                        BrowseMode::Trash => tr!(lang, TrashEmpty),
+                        _ => tr!(lang, DirectoryEmpty, path),
+                    };
+                    let mut buttons = navigation_rows(state, path, mode, lang);
+                    if matches!(mode, BrowseMode::Browse) {
+                        buttons.push(options_row(state, path, &options, lang));
+                    }
+                    let keyboard = InlineKeyboardMarkup::new(buttons);
                     if let Some(mid) = message_id {
-                        bot.edit_message_text(chat_id, mid, text).await?;
+                        bot.edit_message_text(chat_id, mid, text)
+                            .reply_markup(keyboard)
+                            .await?;
                     } else {
-                        bot.send_message(chat_id, text).await?;
+                        bot.send_message(chat_id, text)
+                            .reply_markup(keyboard)
+                            .await?;
                     }
                 } else {
-                    let mut buttons = Vec::new();
+                    let mut buttons = navigation_rows(state, path, mode, lang);
+                    if matches!(mode, BrowseMode::Browse) {
+                        buttons.push(options_row(state, path, &options, lang));
+                    }
 
+                    let mut has_media = false;
+                    let selection = match (mode, message_id) {
+                        (BrowseMode::Select, Some(mid)) => state.selection(chat_id, mid).await,
+                        _ => None,
+                    };
+                    let mut entries = Vec::new();
                     for file in files {
                         let file_type = file
                             .get("type")
                             .and_then(|v| v.as_i64())
                             .unwrap_or_default();
+                        if file_type != 1 && matches!(mode, BrowseMode::Pick(_)) {
+                            continue;
+                        }
+                        let unknown = tr!(lang, Unknown);
                         let name = file
                             .get("name")
                             .and_then(|v| v.as_str())
-                            .unwrap_or("Unknown");
+                            .unwrap_or(&unknown);
 
-                        let icon = if file_type == 1 { "📁" } else { "📄" };
-                        let display_text = format!("{} {}", icon, name);
+                        let size = file
+                            .get("size")
+                            .and_then(|v| v.as_u64())
+                            .unwrap_or_default();
+                        // 只显示日期，按钮宽度有限
+                        let modified = file
+                            .get("updated_at")
+                            .and_then(|v| v.as_str())
+                            .and_then(|v| v.get(..10))
+                            .unwrap_or_default();
+                        let display_text = if file_type == 1 {
+                            format!("📁 {} · {}", name, modified)
+                        } else {
+                            has_media |= is_media(name);
+                            format!(
+                                "{} {} · {} · {}",
+                                file_icon(name),
+                                name,
+                                format_size(size),
+                                modified
+                            )
+                        };
+                        let display_text = display_text.trim_end_matches(" · ").to_string();
                         let full_path = file.get("path").and_then(|v| v.as_str()).unwrap_or(name);
-                        let callback_data = if file_type == 1 {
-                            format!("cd:{}", full_path)
+                        let (display_text, callback_data) = if let Some(selection) = &selection {
+                            let mark = if selection.selected.iter().any(|s| s == full_path) {
+                                "☑️"
+                            } else {
+                                "⬜"
+                            };
+                            entries.push(full_path.to_string());
+                            (
+                                format!("{} {}", mark, display_text),
+                                Action::SelectToggle.data(format!(
+                                    "{}:{}",
+                                    page,
+                                    entries.len() - 1
+                                )),
+                            )
+                        } else if file_type == 1 || matches!(mode, BrowseMode::Trash) {
+                            (display_text, mode.cd_data(state, full_path))
                         } else {
-                            format!("gl:{}", full_path)
+                            (display_text, Action::Details.data(state.path_id(full_path)))
                         };
 
-                        if callback_data.len() <= 64 {
-                            buttons.push(vec![InlineKeyboardButton::callback(
-                                display_text,
-                                callback_data,
-                            )]);
-                        } else {
-                            buttons
-                                .push(vec![InlineKeyboardButton::callback(display_text, "noop")]);
-                        }
+                        buttons.push(vec![InlineKeyboardButton::callback(
+                            display_text,
+                            callback_data,
+                        )]);
                     }
 
                     // Pagination buttons
                     let mut pagination_row = Vec::new();
                     if page > 0 {
                         pagination_row.push(InlineKeyboardButton::callback(
-                            "⬅️ Prev",
-                            format!("pg:{}:{}", path, page - 1),
+                            tr!(lang, BtnPrev),
+                            mode.page_data(state, path, page - 1),
                         ));
                     }
 
                     if next_token.is_some() || files.len() as u32 == page_size {
                         pagination_row.push(InlineKeyboardButton::callback(
-                            "Next ➡️",
-                            format!("pg:{}:{}", path, page + 1),
+                            tr!(lang, BtnNext),
+                            mode.page_data(state, path, page + 1),
                         ));
                     }
 
@@ -365,21 +3524,105 @@ pub async fn list_files_and_send(
                         buttons.push(pagination_row);
                     }
 
+                    if matches!(mode, BrowseMode::Browse) {
+                        let mut tools_row = vec![path_button(
+                            state,
+                            tr!(lang, BtnSelect),
+                            Action::Select,
+                            path,
+                        )];
+                        if has_media {
+                            tools_row.push(InlineKeyboardButton::callback(
+                                tr!(lang, BtnPreview),
+                                Action::Preview.data(format!("{}:{}", page, state.path_id(path))),
+                            ));
+                        }
+                        buttons.push(tools_row);
+                        buttons.push(vec![
+                            path_button(
+                                state,
+                                tr!(lang, BtnExportLinks),
+                                Action::ExportLinks,
+                                path,
+                            ),
+                            path_button(state, tr!(lang, BtnStats), Action::Stats, path),
+                        ]);
+                    }
+
+                    let selected_count = selection.as_ref().map_or(0, |s| s.selected.len());
+                    if let (Some(_), Some(mid)) = (&selection, message_id) {
+                        state.set_selection_entries(chat_id, mid, entries).await;
+                        buttons.extend(selection_bar(lang));
+                    }
+
+                    if matches!(mode, BrowseMode::Trash) {
+                        buttons.push(vec![InlineKeyboardButton::callback(
+                            tr!(lang, BtnEmptyTrash),
+                            Action::EmptyTrash.data(""),
+                        )]);
+                    }
+
+                    if let BrowseMode::Pick(id) = mode {
+                        buttons.push(vec![
+                            InlineKeyboardButton::callback(
+                                tr!(lang, BtnSaveHere),
+                                Action::PickSave.data(id),
+                            ),
+                            InlineKeyboardButton::callback(
+                                tr!(lang, BtnCancelPick),
+                                Action::PickCancel.data(id),
+                            ),
+                        ]);
+                    }
+
                     let keyboard = InlineKeyboardMarkup::new(buttons);
-                    let text = format!("Files in `{}` (Page {}):", path, page);
+                    let mut text = match mode {
+                        BrowseMode::Browse => tr!(lang, ListHeader, path, page),
+                        BrowseMode::Pick(_) => tr!(lang, PickHeader, path, page),
+                        BrowseMode::Select => {
+                            tr!(lang, SelectHeader, path, page, selected_count)
+                        }
+                        BrowseMode::Trash => tr!(lang, TrashHeader, page),
+                    };
+                    // 合计整个目录而不是当前页；没有缓存时在后台统计，统计失败时不显示
+                    let mut count_later = false;
+                    if matches!(mode, BrowseMode::Browse | BrowseMode::Trash) {
+                        let key = folder_totals_key(path, options.filter);
+                        match state.folder_totals(&key).await {
+                            Some(totals) => text.push_str(&folder_totals_text(&totals, lang)),
+                            None => count_later = true,
+                        }
+                    }
 
-                    if let Some(mid) = message_id {
-                        bot.edit_message_text(chat_id, mid, text)
-                            .reply_markup(keyboard)
+                    let mid = if let Some(mid) = message_id {
+                        bot.edit_message_text(chat_id, mid, text.clone())
+                            .reply_markup(keyboard.clone())
                             .await?;
+                        mid
                     } else {
-                        bot.send_message(chat_id, text)
-                            .reply_markup(keyboard)
-                            .await?;
+                        bot.send_message(chat_id, text.clone())
+                            .reply_markup(keyboard.clone())
+                            .await?
+                            .id
+                    };
+                    if count_later {
+                        spawn_folder_totals(
+                            bot.clone(),
+                            chat_id,
+                            mid,
+                            client.clone(),
+                            state.clone(),
+                            path.to_string(),
+                            options.filter,
+                            text,
+                            keyboard,
+                            lang,
+                        )
+                        .await;
                     }
                 }
             } else {
-                let text = "Failed to parse file list.";
+                let text = tr!(lang, ParseListFailed);
                 if let Some(mid) = message_id {
                     bot.edit_message_text(chat_id, mid, text).await?;
                 } else {
@@ -388,7 +3631,7 @@ pub async fn list_files_and_send(
             }
         }
         Err(e) => {
-            let text = format!("Error listing files: {}", e);
+            let text = tr!(lang, ListFailed, e);
             if let Some(mid) = message_id {
                 bot.edit_message_text(chat_id, mid, text).await?;
             } else {
@@ -399,18 +3642,127 @@ pub async fn list_files_and_send(
     Ok(())
 }
 
-pub async fn answer_message_by_link(bot: Bot, msg: Message, url: &str) -> ResponseResult<()> {
-    let source_link = match get_source_link(url).await {
-        Ok(source_link) => source_link,
-        Err(e) => format!("解析链接失败: {}", e),
+pub async fn answer_message_by_link(
+    bot: Bot,
+    msg: Message,
+    url: &str,
+    state: &StateStore,
+    resolvers: &ResolverRegistry,
+    lang: Lang,
+) -> ResponseResult<()> {
+    let (source_link, keyboard) = match resolvers.resolve(url).await {
+        Ok(source_link) => {
+            let id = state
+                .add_pending_download(DownloadSource::Url(source_link.clone()))
+                .await;
+            let keyboard =
+                download_keyboard(state, id, msg.from.as_ref().map(|u| u.id), lang).await;
+            (source_link, Some(keyboard))
+        }
+        Err(e) => (tr!(lang, LinkResolveFailed, e), None),
     };
-    let text = format!("单击下面的链接可直接复制：\n<code>{}</code>", source_link);
-    let mut buttons = Vec::new();
-    buttons.push(vec![InlineKeyboardButton::callback(
-        "提交云盘下载",
-        format!("rd:{}", source_link),
-    )]);
-    let keyboard = InlineKeyboardMarkup::new(buttons);
+    let text = tr!(lang, LinkText, html::escape(&source_link));
+    let mut request = bot
+        .send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_parameters(ReplyParameters::new(msg.id));
+    if let Some(keyboard) = keyboard {
+        request = request.reply_markup(keyboard);
+    }
+    request.await?;
+    Ok(())
+}
+
+pub async fn answer_message_by_links(
+    bot: Bot,
+    msg: Message,
+    urls: &[String],
+    state: &StateStore,
+    resolvers: &ResolverRegistry,
+    lang: Lang,
+) -> ResponseResult<()> {
+    if let [url] = urls {
+        return answer_message_by_link(bot, msg, url, state, resolvers, lang).await;
+    }
+
+    let resolved: Vec<(String, Result<String>)> = stream::iter(urls.iter().cloned())
+        .map(|url| async move {
+            let link = resolvers.resolve(&url).await;
+            (url, link)
+        })
+        .buffered(RESOLVE_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut lines = Vec::new();
+    let mut links = Vec::new();
+    for (i, (url, link)) in resolved.into_iter().enumerate() {
+        match link {
+            Ok(link) => {
+                lines.push(format!("{}. <code>{}</code>", i + 1, html::escape(&link)));
+                links.push(link);
+            }
+            Err(e) => lines.push(format!(
+                "{}. {}\n{}",
+                i + 1,
+                tr!(lang, LinkResolveFailed, html::escape(&e.to_string())),
+                html::escape(&url)
+            )),
+        }
+    }
+    let text = tr!(
+        lang,
+        LinksResolved,
+        links.len(),
+        urls.len(),
+        lines.join("\n")
+    );
+
+    let mut request = bot
+        .send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_parameters(ReplyParameters::new(msg.id));
+    if !links.is_empty() {
+        let id = state
+            .add_pending_download(DownloadSource::Batch(links))
+            .await;
+        request = request.reply_markup(
+            download_keyboard(state, id, msg.from.as_ref().map(|u| u.id), lang).await,
+        );
+    }
+    request.await?;
+    Ok(())
+}
+
+pub async fn answer_message_by_magnet(
+    bot: Bot,
+    msg: Message,
+    magnet: &str,
+    state: &StateStore,
+    lang: Lang,
+) -> ResponseResult<()> {
+    // 磁力链接的 dn 参数即为资源名称
+    let name = magnet
+        .split_once('?')
+        .map(|(_, query)| query)
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("dn="))
+        .map(|dn| {
+            urlencoding::decode(&dn.replace('+', " "))
+                .map(|s| s.into_owned())
+                .unwrap_or_else(|_| dn.to_string())
+        });
+    let id = state
+        .add_pending_download(DownloadSource::Url(magnet.to_string()))
+        .await;
+    let text = tr!(
+        lang,
+        MagnetDetected,
+        html::escape(&name.unwrap_or_else(|| tr!(lang, Unknown))),
+        html::escape(magnet)
+    );
+    let keyboard = download_keyboard(state, id, msg.from.as_ref().map(|u| u.id), lang).await;
     bot.send_message(msg.chat.id, text)
         .parse_mode(teloxide::types::ParseMode::Html)
         .reply_parameters(ReplyParameters::new(msg.id))
@@ -419,60 +3771,597 @@ pub async fn answer_message_by_link(bot: Bot, msg: Message, url: &str) -> Respon
     Ok(())
 }
 
-pub async fn message_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
-    if let Some(text) = msg.text() {
-        for entity in msg.entities().unwrap_or(&[]) {
-            if let teloxide::types::MessageEntityKind::Url
-            | teloxide::types::MessageEntityKind::TextLink { .. } = &entity.kind
-            {
-                let url = match &entity.kind {
-                    teloxide::types::MessageEntityKind::Url => {
-                        text[entity.offset..(entity.offset + entity.length)].to_string()
-                    }
-                    teloxide::types::MessageEntityKind::TextLink { url } => url.to_string(),
-                    _ => continue,
-                };
-                if url.contains("t.me") {
-                    answer_message_by_link(bot.clone(), msg.clone(), &url).await?;
-                }
+pub async fn answer_message_by_torrent(
+    bot: Bot,
+    msg: Message,
+    document: &Document,
+    state: &StateStore,
+    lang: Lang,
+) -> ResponseResult<()> {
+    let file_name = document
+        .file_name
+        .clone()
+        .unwrap_or_else(|| format!("{}.torrent", document.file.unique_id));
+    let id = state
+        .add_pending_download(DownloadSource::Torrent {
+            file_id: document.file.id.clone(),
+            file_name: file_name.clone(),
+        })
+        .await;
+    let text = tr!(lang, TorrentDetected, html::escape(&file_name));
+    let keyboard = download_keyboard(state, id, msg.from.as_ref().map(|u| u.id), lang).await;
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_parameters(ReplyParameters::new(msg.id))
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+pub struct UrlProbe {
+    pub file_name: Option<String>,
+    pub size: Option<u64>,
+    pub mime_type: Option<String>,
+}
+
+// 探测链接时最多跟随的重定向次数
+const PROBE_MAX_REDIRECTS: usize = 5;
+
+// 通过 HEAD 请求获取文件名、大小和类型；只访问公网地址，重定向逐跳检查
+pub async fn probe_url(url: &str) -> Result<UrlProbe> {
+    let mut url = reqwest::Url::parse(url)?;
+    for _ in 0..=PROBE_MAX_REDIRECTS {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("Unsupported scheme: {}", url.scheme()));
+        }
+        // 固定使用检查过的地址，避免发送请求时再次解析到内网地址
+        let addr = public_addr(&url).await?;
+        let mut builder = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(Duration::from_secs(10));
+        if let Some(domain) = url.domain() {
+            builder = builder.resolve(domain, addr);
+        }
+        let resp = builder.build()?.head(url.clone()).send().await?;
+        let status = resp.status();
+        if status.is_redirection() {
+            let location = resp
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| anyhow!("Redirect without location: {}", status))?;
+            url = url.join(location)?;
+            continue;
+        }
+        if !status.is_success() {
+            return Err(anyhow!("HTTP {}", status));
+        }
+        return Ok(response_probe(&resp));
+    }
+    Err(anyhow!("Too many redirects"))
+}
+
+// 解析链接的主机，任一地址不是公网地址时拒绝
+async fn public_addr(url: &reqwest::Url) -> Result<SocketAddr> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("URL without host"))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+    if addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+        return Err(anyhow!("Refusing to access a private address: {}", host));
+    }
+    addrs
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("No address for {}", host))
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // 0.0.0.0/8 和运营商级 NAT 100.64.0.0/10
+                || a == 0
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // fc00::/7 唯一本地地址, fe80::/10 链路本地地址
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+fn response_probe(resp: &reqwest::Response) -> UrlProbe {
+    let headers = resp.headers();
+    let header = |name: reqwest::header::HeaderName| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+
+    let file_name = header(reqwest::header::CONTENT_DISPOSITION)
+        .and_then(|v| content_disposition_file_name(&v))
+        .or_else(|| {
+            resp.url()
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .filter(|segment| !segment.is_empty())
+                .map(|segment| {
+                    urlencoding::decode(segment)
+                        .map(|s| s.into_owned())
+                        .unwrap_or_else(|_| segment.to_string())
+                })
+        });
+    let size = header(reqwest::header::CONTENT_LENGTH).and_then(|v| v.parse::<u64>().ok());
+    let mime_type = header(reqwest::header::CONTENT_TYPE);
+
+    UrlProbe {
+        file_name,
+        size,
+        mime_type,
+    }
+}
+
+fn content_disposition_file_name(value: &str) -> Option<String> {
+    let params: Vec<&str> = value.split(';').map(|p| p.trim()).collect();
+    // filename* (RFC 5987) 优先于 filename
+    if let Some(encoded) = params.iter().find_map(|p| p.strip_prefix("filename*=")) {
+        let encoded = encoded.trim_matches('"');
+        let encoded = encoded
+            .split_once("''")
+            .map(|(_, name)| name)
+            .unwrap_or(encoded);
+        if let Ok(name) = urlencoding::decode(encoded) {
+            return Some(name.into_owned());
+        }
+    }
+    params
+        .iter()
+        .find_map(|p| p.strip_prefix("filename="))
+        .map(|name| name.trim_matches('"').to_string())
+        .filter(|name| !name.is_empty())
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.2} {}", size, UNITS[unit])
+    }
+}
+
+pub async fn answer_message_by_url(
+    bot: Bot,
+    msg: Message,
+    url: &str,
+    state: &StateStore,
+    lang: Lang,
+) -> ResponseResult<()> {
+    let text = match probe_url(url).await {
+        Ok(probe) => tr!(
+            lang,
+            UrlInfo,
+            html::escape(&probe.file_name.unwrap_or_else(|| tr!(lang, Unknown))),
+            probe.size.map_or(tr!(lang, Unknown), format_size),
+            html::escape(&probe.mime_type.unwrap_or_else(|| tr!(lang, Unknown))),
+            html::escape(url)
+        ),
+        Err(e) => format!(
+            "{}\n<code>{}</code>",
+            tr!(lang, GetFileInfoFailed, html::escape(&e.to_string())),
+            html::escape(url)
+        ),
+    };
+    let id = state
+        .add_pending_download(DownloadSource::Url(url.to_string()))
+        .await;
+    let keyboard = download_keyboard(state, id, msg.from.as_ref().map(|u| u.id), lang).await;
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_parameters(ReplyParameters::new(msg.id))
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+// Bot API 只能下载不超过 20MB 的文件
+const TELEGRAM_DOWNLOAD_LIMIT: u32 = 20 * 1024 * 1024;
+// Bot API 上传文件不超过 50MB
+const TELEGRAM_UPLOAD_LIMIT: u64 = 50 * 1024 * 1024;
+
+// 把云盘文件下载到内存后发送；InputFile::url 只支持 GIF、PDF 和 ZIP
+async fn send_file_to_chat(
+    bot: &Bot,
+    chat_id: ChatId,
+    client: &CloudreveClient,
+    path: &str,
+    lang: Lang,
+) -> Result<()> {
+    let url = client.file_source(path, DOWNLOAD_LINK).await?.url;
+    let mut resp = reqwest::get(&url).await?.error_for_status()?;
+    let too_large = |size: u64| anyhow!(tr!(lang, SendFileTooLarge, format_size(size)));
+    if let Some(size) = resp
+        .content_length()
+        .filter(|&size| size > TELEGRAM_UPLOAD_LIMIT)
+    {
+        return Err(too_large(size));
+    }
+    let mut content = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        content.extend_from_slice(&chunk);
+        if content.len() as u64 > TELEGRAM_UPLOAD_LIMIT {
+            return Err(too_large(content.len() as u64));
+        }
+    }
+    let file = InputFile::memory(content).file_name(uri::display_name(path));
+    bot.send_document(chat_id, file).await?;
+    Ok(())
+}
+
+// 消息中附带的文件；没有文件名的媒体以 file_unique_id 命名
+fn message_file(msg: &Message) -> Option<TelegramFile> {
+    let named = |meta: &teloxide::types::FileMeta, name: Option<&String>, ext: &str| TelegramFile {
+        file_id: meta.id.clone(),
+        file_name: name
+            .cloned()
+            .unwrap_or_else(|| format!("{}.{}", meta.unique_id, ext)),
+        size: meta.size,
+    };
+    if let Some(document) = msg.document() {
+        return Some(named(&document.file, document.file_name.as_ref(), "bin"));
+    }
+    if let Some(video) = msg.video() {
+        return Some(named(&video.file, video.file_name.as_ref(), "mp4"));
+    }
+    if let Some(audio) = msg.audio() {
+        return Some(named(&audio.file, audio.file_name.as_ref(), "mp3"));
+    }
+    if let Some(animation) = msg.animation() {
+        return Some(named(&animation.file, animation.file_name.as_ref(), "mp4"));
+    }
+    if let Some(voice) = msg.voice() {
+        return Some(named(&voice.file, None, "ogg"));
+    }
+    if let Some(video_note) = msg.video_note() {
+        return Some(named(&video_note.file, None, "mp4"));
+    }
+    // 照片取最大尺寸
+    msg.photo()
+        .and_then(|sizes| sizes.iter().max_by_key(|p| p.width * p.height))
+        .map(|photo| named(&photo.file, None, "jpg"))
+}
+
+// 提供保存转发消息或相册附带的文件，相册只回复一次
+pub async fn answer_message_by_files(
+    bot: Bot,
+    msg: Message,
+    files: Vec<TelegramFile>,
+    state: &StateStore,
+    lang: Lang,
+) -> ResponseResult<()> {
+    let text = match files.as_slice() {
+        [file] => tr!(
+            lang,
+            FileDetected,
+            html::escape(&file.file_name),
+            format_size(file.size as u64)
+        ),
+        _ => {
+            let names: Vec<String> = files
+                .iter()
+                .map(|file| format!("<b>{}</b>", html::escape(&file.file_name)))
+                .collect();
+            let total: u64 = files.iter().map(|file| file.size as u64).sum();
+            tr!(
+                lang,
+                FilesDetected,
+                files.len(),
+                format_size(total),
+                names.join("\n")
+            )
+        }
+    };
+    let id = state
+        .add_pending_download(DownloadSource::TelegramFiles(files))
+        .await;
+    let keyboard = download_keyboard(state, id, msg.from.as_ref().map(|u| u.id), lang).await;
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_parameters(ReplyParameters::new(msg.id))
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+// 转发消息在来源频道中的链接；私有频道使用 t.me/c/ 形式
+fn forward_origin_link(origin: &MessageOrigin) -> Option<String> {
+    match origin {
+        MessageOrigin::Channel {
+            chat, message_id, ..
+        } => match chat.username() {
+            Some(username) => Some(format!("https://t.me/{}/{}", username, message_id)),
+            None => {
+                // 频道 id 形如 -100xxxxxxxxxx，t.me/c/ 使用去掉 -100 前缀的部分
+                let internal_id = -(chat.id.0 + 1_000_000_000_000);
+                (internal_id > 0).then(|| format!("https://t.me/c/{}/{}", internal_id, message_id))
             }
+        },
+        // 群组匿名管理员、用户转发的消息没有可用的消息链接
+        MessageOrigin::Chat { .. }
+        | MessageOrigin::User { .. }
+        | MessageOrigin::HiddenUser { .. } => None,
+    }
+}
+
+fn is_torrent(document: &Document) -> bool {
+    let by_name = document
+        .file_name
+        .as_deref()
+        .map(|name| name.to_lowercase().ends_with(".torrent"))
+        .unwrap_or(false);
+    let by_mime = document
+        .mime_type
+        .as_ref()
+        .map(|mime| mime.essence_str() == "application/x-bittorrent")
+        .unwrap_or(false);
+    by_name || by_mime
+}
+
+// 消息正文和说明中的所有链接
+fn message_urls(msg: &Message) -> Vec<String> {
+    msg.parse_entities()
+        .into_iter()
+        .chain(msg.parse_caption_entities())
+        .flatten()
+        .filter_map(|entity| match entity.kind() {
+            MessageEntityKind::Url => Some(entity.text().to_string()),
+            MessageEntityKind::TextLink { url } => Some(url.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+pub async fn message_handler(
+    bot: Bot,
+    msg: Message,
+    state: StateStore,
+    resolvers: Arc<ResolverRegistry>,
+    client: Arc<CloudreveClient>,
+) -> ResponseResult<()> {
+    let lang = state.lang(msg.from.as_ref()).await;
+    // 回复机器人的提示消息，例如输入新的文件名
+    if let Some(reply) = msg.reply_to_message() {
+        if let Some(input) = state.take_pending_input(msg.chat.id, reply.id).await {
+            return handle_pending_input(bot, msg, input, client, lang).await;
         }
     }
+
+    // 需要解析的链接统一收集起来，多个链接合并为一次批量下载
+    let auto_offer = state.chat_settings(msg.chat.id).await.auto_offer;
+    let mut links = Vec::new();
+    for url in message_urls(&msg) {
+        if resolvers.matches(&url) {
+            links.push(url);
+        } else if auto_offer && (url.starts_with("http://") || url.starts_with("https://")) {
+            answer_message_by_url(bot.clone(), msg.clone(), &url, &state, lang).await?;
+        }
+    }
+    if let Some(text) = msg.text().or(msg.caption()) {
+        for magnet in MAGNET_RE.find_iter(text) {
+            answer_message_by_magnet(bot.clone(), msg.clone(), magnet.as_str(), &state, lang)
+                .await?;
+        }
+    }
+    if let Some(document) = msg.document().filter(|d| is_torrent(d)) {
+        answer_message_by_torrent(bot.clone(), msg.clone(), document, &state, lang).await?;
+    }
+    let mut files = Vec::new();
     if let Some(origin) = msg.forward_origin() {
-        match origin {
-            teloxide::types::MessageOrigin::Channel {
-                chat, message_id, ..
-            } => {
-                if let Some(username) = chat.username() {
-                    let url = format!("https://t.me/{}/{}", username, message_id);
-                    answer_message_by_link(bot.clone(), msg.clone(), &url).await?;
-                }
+        // 附带的文件直接提供保存，有来源链接时一并交给解析器；种子文件已在上面处理
+        // 超出 Bot API 下载上限的文件只能通过来源链接获取
+        let is_torrent_document = msg.document().map(is_torrent).unwrap_or(false);
+        let file = message_file(&msg).filter(|_| !is_torrent_document);
+        let origin_link = forward_origin_link(origin);
+        match file {
+            Some(file) if file.size <= TELEGRAM_DOWNLOAD_LIMIT => files.push(file),
+            Some(file) if origin_link.is_none() => {
+                bot.send_message(
+                    msg.chat.id,
+                    tr!(lang, FileTooLarge, format_size(file.size as u64)),
+                )
+                .reply_parameters(ReplyParameters::new(msg.id))
+                .await?;
             }
-            _ => {
-                bot.send_message(msg.chat.id, "无法解析来源链接".to_string())
+            None if origin_link.is_none() && !is_torrent_document => {
+                bot.send_message(msg.chat.id, tr!(lang, NoSourceLink))
                     .reply_parameters(ReplyParameters::new(msg.id))
                     .await?;
             }
+            _ => {}
+        }
+        links.extend(origin_link);
+    }
+
+    if let Some(group_id) = msg.media_group_id() {
+        // 相册的每条消息单独到达，由第一条消息等待其余消息后统一处理
+        if state.collect_media_group(group_id, links, files).await {
+            let group_id = group_id.to_string();
+            tokio::spawn(async move {
+                sleep(MEDIA_GROUP_DELAY).await;
+                let group = state.take_media_group(&group_id).await;
+                if !group.files.is_empty() {
+                    if let Err(e) =
+                        answer_message_by_files(bot.clone(), msg.clone(), group.files, &state, lang)
+                            .await
+                    {
+                        error!("Failed to offer files of media group {}: {}", group_id, e);
+                    }
+                }
+                if group.links.is_empty() {
+                    return;
+                }
+                if let Err(e) =
+                    answer_message_by_links(bot, msg, &group.links, &state, &resolvers, lang).await
+                {
+                    error!("Failed to answer media group {}: {}", group_id, e);
+                }
+            });
         }
+        return Ok(());
+    }
+    if !files.is_empty() {
+        answer_message_by_files(bot.clone(), msg.clone(), files, &state, lang).await?;
+    }
+    if !links.is_empty() {
+        answer_message_by_links(bot.clone(), msg.clone(), &links, &state, &resolvers, lang).await?;
     }
     Ok(())
 }
 
-pub async fn get_source_link(url: &str) -> Result<String> {
-    let resp = reqwest::get(format!("{}/api/resolve?url={}", "https://tg.taro.cat", url)).await?;
-    let text = resp.text().await?;
-    let api_resp: Value = serde_json::from_str(&text)
-        .map_err(|e| anyhow!("Failed to parse response: {} - {}", e, text))?;
-    if !api_resp
-        .get("ok")
-        .and_then(|v| v.as_bool())
-        .unwrap_or(false)
-    {
-        return Err(anyhow!("Failed to resolve URL"));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_disposition_prefers_encoded_file_name() {
+        assert_eq!(
+            content_disposition_file_name(
+                "attachment; filename=\"fallback.txt\"; filename*=UTF-8''%E6%96%87%E4%BB%B6.txt"
+            )
+            .as_deref(),
+            Some("文件.txt")
+        );
+        assert_eq!(
+            content_disposition_file_name("attachment; filename=\"a b.zip\"").as_deref(),
+            Some("a b.zip")
+        );
+        assert_eq!(content_disposition_file_name("inline"), None);
+        assert_eq!(
+            content_disposition_file_name("attachment; filename=\"\""),
+            None
+        );
     }
-    if let Some(download_url) = api_resp.get("stream_link").and_then(|v| v.as_str()) {
-        Ok(download_url.to_string())
-    } else {
-        Err(anyhow!("Failed to resolve URL"))
+
+    #[test]
+    fn format_time_keeps_date_and_minutes() {
+        assert_eq!(
+            format_time("2024-05-01T08:30:59.123456+08:00"),
+            "2024-05-01 08:30"
+        );
+        assert_eq!(format_time("2024-05-01"), "2024-05-01");
+        assert_eq!(format_time(""), "");
+    }
+
+    #[test]
+    fn forward_origin_link_uses_channel_username_or_internal_id() {
+        let channel = |chat: serde_json::Value| -> MessageOrigin {
+            serde_json::from_value(serde_json::json!({
+                "type": "channel",
+                "date": 0,
+                "chat": chat,
+                "message_id": 42,
+            }))
+            .unwrap()
+        };
+        let public = channel(serde_json::json!({
+            "id": -1001234567890i64,
+            "type": "channel",
+            "title": "News",
+            "username": "news",
+        }));
+        assert_eq!(
+            forward_origin_link(&public).as_deref(),
+            Some("https://t.me/news/42")
+        );
+        let private = channel(serde_json::json!({
+            "id": -1001234567890i64,
+            "type": "channel",
+            "title": "Private",
+        }));
+        assert_eq!(
+            forward_origin_link(&private).as_deref(),
+            Some("https://t.me/c/1234567890/42")
+        );
+    }
+
+    #[test]
+    fn action_round_trips_through_callback_data() {
+        let data = Action::Preview.data("2:17");
+        let (code, payload) = data.split_once(':').unwrap();
+        assert_eq!(Action::parse(code), Some(Action::Preview));
+        assert_eq!(payload.rsplit_once(':'), Some(("2", "17")));
+        assert_eq!(Action::parse("zz"), None);
+        assert!(Action::Open.needs_path() && !Action::Open.admin_only());
+        assert!(Action::EmptyTrash.admin_only() && !Action::EmptyTrash.needs_path());
+    }
+
+    #[test]
+    fn next_list_options_cycles_one_option() {
+        let options = ListOptions::default();
+        let sorted = next_list_options(Action::SortBy, options);
+        assert_eq!(sorted.order_by, OrderBy::Size);
+        assert_eq!(sorted.filter, options.filter);
+        assert_eq!(
+            next_list_options(Action::SortDirection, options).order_direction,
+            OrderDirection::Desc
+        );
+        assert_eq!(
+            next_list_options(Action::Filter, options).filter,
+            FileFilter::Folders
+        );
+
+        let mut filter = options;
+        for _ in 0..7 {
+            filter = next_list_options(Action::Filter, filter);
+        }
+        assert_eq!(filter, options);
+    }
+
+    #[test]
+    fn private_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:192.168.1.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["1.1.1.1", "100.128.0.1", "2606:4700::1111"] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
     }
 }
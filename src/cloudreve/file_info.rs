@@ -0,0 +1,29 @@
+use crate::cloudreve::{CloudreveClient, DirectoryResponse};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+impl CloudreveClient {
+    // 获取文件详情，包含存储策略、分享等扩展信息
+    pub async fn get_file_info(&self, uri: &str) -> Result<Value> {
+        let resp = self
+            .request_builder(reqwest::Method::GET, "/file/info")
+            .await
+            .query(&[("uri", uri), ("extended", "true")])
+            .send()
+            .await?;
+
+        let text = resp.text().await?;
+
+        let api_resp: DirectoryResponse = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("Failed to parse file info response: {} - {}", e, text))?;
+
+        if api_resp.code == 0 {
+            Ok(api_resp.data.unwrap_or_default())
+        } else {
+            Err(anyhow!(
+                "Failed to get file info: {}",
+                api_resp.msg.unwrap_or_default()
+            ))
+        }
+    }
+}
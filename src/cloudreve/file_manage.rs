@@ -0,0 +1,119 @@
+use crate::cloudreve::{uri, CloudreveClient, DirectoryResponse};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+const EMPTY_TRASH_PAGE_SIZE: u32 = 100;
+const EMPTY_TRASH_MAX_ROUNDS: usize = 50;
+
+impl CloudreveClient {
+    pub async fn rename_file(&self, uri: &str, new_name: &str) -> Result<()> {
+        let body = serde_json::json!({
+            "uri": uri,
+            "new_name": new_name,
+        });
+        self.file_operation(reqwest::Method::POST, "/file/rename", &body, "rename")
+            .await
+    }
+
+    // copy 为 true 时复制，否则移动
+    pub async fn move_files(&self, uris: &[String], dst: &str, copy: bool) -> Result<()> {
+        let body = serde_json::json!({
+            "uris": uris,
+            "dst": dst,
+            "copy": copy,
+        });
+        self.file_operation(reqwest::Method::POST, "/file/move", &body, "move")
+            .await
+    }
+
+    // 删除到回收站
+    pub async fn delete_files(&self, uris: &[String]) -> Result<()> {
+        let body = serde_json::json!({
+            "uris": uris,
+        });
+        self.file_operation(reqwest::Method::DELETE, "/file", &body, "delete")
+            .await
+    }
+
+    // 从回收站恢复到原位置
+    pub async fn restore_files(&self, uris: &[String]) -> Result<()> {
+        let body = serde_json::json!({
+            "uris": uris,
+        });
+        self.file_operation(reqwest::Method::POST, "/file/restore", &body, "restore")
+            .await
+    }
+
+    // 彻底删除，不经过回收站
+    pub async fn purge_files(&self, uris: &[String]) -> Result<()> {
+        let body = serde_json::json!({
+            "uris": uris,
+            "skip_soft_delete": true,
+        });
+        self.file_operation(reqwest::Method::DELETE, "/file", &body, "purge")
+            .await
+    }
+
+    // 清空回收站，每次取一页彻底删除直到为空
+    pub async fn empty_trash(&self) -> Result<usize> {
+        let mut purged = 0;
+        for _ in 0..EMPTY_TRASH_MAX_ROUNDS {
+            let (data, _) = self
+                .list_files()
+                .uri(uri::TRASH)
+                .page_size(EMPTY_TRASH_PAGE_SIZE)
+                .send()
+                .await?;
+            let uris: Vec<String> = data
+                .get("files")
+                .and_then(|f| f.as_array())
+                .map(|files| {
+                    files
+                        .iter()
+                        .filter_map(|f| f.get("path").and_then(|v| v.as_str()))
+                        .map(|s| s.to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            if uris.is_empty() {
+                return Ok(purged);
+            }
+            self.purge_files(&uris).await?;
+            purged += uris.len();
+        }
+        Err(anyhow!(
+            "Trash still not empty after purging {} items",
+            purged
+        ))
+    }
+
+    async fn file_operation(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: &Value,
+        name: &str,
+    ) -> Result<()> {
+        let resp = self
+            .request_builder(method, path)
+            .await
+            .json(body)
+            .send()
+            .await?;
+
+        let text = resp.text().await?;
+
+        let api_resp: DirectoryResponse = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("Failed to parse {} response: {} - {}", name, e, text))?;
+
+        if api_resp.code == 0 {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Failed to {} file: {}",
+                name,
+                api_resp.msg.unwrap_or_default()
+            ))
+        }
+    }
+}
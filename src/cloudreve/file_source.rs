@@ -1,14 +1,131 @@
 use crate::cloudreve::{CloudreveClient, DirectoryResponse};
 use anyhow::{anyhow, Result};
+use log::info;
+use serde_json::Value;
+use std::collections::HashMap;
+
+// 每次 /file/url 请求的文件数
+const SOURCE_BATCH_SIZE: usize = 50;
+
+/// Options of the links created by `list_file_source`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SourceOptions {
+    /// Links that download the file instead of previewing it in the browser.
+    pub download: bool,
+    /// A single zip link for all uris instead of one link per file.
+    pub archive: bool,
+    /// Sign new links instead of reusing cached ones, restarting their lifetime.
+    /// `/file/url` has no parameter for the lifetime itself: it comes from the
+    /// storage policy on the server and is reported in `FileSource::expires`.
+    pub no_cache: bool,
+}
+
+/// A signed link to a file.
+#[derive(Clone, Debug)]
+pub struct FileSource {
+    pub url: String,
+    /// Expiry time as returned by Cloudreve, `None` for links that do not expire.
+    pub expires: Option<String>,
+}
 
 impl CloudreveClient {
-    pub async fn list_file_source(&self, uri: &str) -> Result<String> {
+    /// Links for many files, keyed by uri. With more than one uri, files whose
+    /// link cannot be created are left out instead of failing the whole batch.
+    /// In archive mode every uri maps to the same zip link.
+    pub async fn list_file_source(
+        &self,
+        uris: &[String],
+        options: SourceOptions,
+    ) -> Result<HashMap<String, FileSource>> {
+        let mut sources = HashMap::new();
+        if options.archive {
+            let (urls, expires) = self.file_urls(uris, options).await?;
+            let url = urls
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("No file source found"))?;
+            for uri in uris {
+                sources.insert(
+                    uri.clone(),
+                    FileSource {
+                        url: url.clone(),
+                        expires: expires.clone(),
+                    },
+                );
+            }
+            return Ok(sources);
+        }
+
+        for chunk in uris.chunks(SOURCE_BATCH_SIZE) {
+            // 结果按请求顺序返回，数量不一致时无法对应，按失败处理
+            let result = self
+                .file_urls(chunk, options)
+                .await
+                .and_then(|(urls, expires)| {
+                    if urls.len() == chunk.len() {
+                        Ok((urls, expires))
+                    } else {
+                        Err(anyhow!(
+                            "Expected {} file sources, got {}",
+                            chunk.len(),
+                            urls.len()
+                        ))
+                    }
+                });
+            match result {
+                Ok((urls, expires)) => {
+                    for (uri, url) in chunk.iter().zip(urls) {
+                        sources.insert(
+                            uri.clone(),
+                            FileSource {
+                                url,
+                                expires: expires.clone(),
+                            },
+                        );
+                    }
+                }
+                Err(e) if uris.len() == 1 => return Err(e),
+                Err(e) => {
+                    info!("Batch file source failed, retrying one by one: {}", e);
+                    // 一个文件失败会导致整批失败，逐个重试以找出失败的文件
+                    for uri in chunk.iter().filter(|_| chunk.len() > 1) {
+                        match self.file_urls(std::slice::from_ref(uri), options).await {
+                            Ok((urls, expires)) => {
+                                if let Some(url) = urls.into_iter().next() {
+                                    sources.insert(uri.clone(), FileSource { url, expires });
+                                }
+                            }
+                            Err(e) => info!("No file source for {}: {}", uri, e),
+                        }
+                    }
+                }
+            }
+        }
+        Ok(sources)
+    }
+
+    /// Link for a single file.
+    pub async fn file_source(&self, uri: &str, options: SourceOptions) -> Result<FileSource> {
+        self.list_file_source(&[uri.to_string()], options)
+            .await?
+            .remove(uri)
+            .ok_or_else(|| anyhow!("No file source found"))
+    }
+
+    async fn file_urls(
+        &self,
+        uris: &[String],
+        options: SourceOptions,
+    ) -> Result<(Vec<String>, Option<String>)> {
         let body = serde_json::json!({
-            "uris": [uri],
+            "uris": uris,
+            "download": options.download,
+            "archive": options.archive,
+            "no_cache": options.no_cache,
         });
 
         let resp = self
-            .request_builder(reqwest::Method::PUT, "/file/source")
+            .request_builder(reqwest::Method::POST, "/file/url")
             .await
             .json(&body)
             .send()
@@ -20,19 +137,31 @@ impl CloudreveClient {
             .map_err(|e| anyhow!("Failed to parse directory response: {} - {}", e, text))?;
 
         if api_resp.code == 0 {
-            if let Some(data) = api_resp.data {
-                if let Some(files) = data.as_array() {
-                    if let Some(first_file) = files.first() {
-                        if let Some(url) = first_file.get("url").and_then(|v| v.as_str()) {
-                            return Ok(url.to_string());
-                        }
-                    }
-                }
-            }
-            Err(anyhow!("No file source found"))
+            let data = api_resp.data.unwrap_or_default();
+            // 缺少 url 的条目会打乱顺序，整批视为失败
+            let urls = data
+                .get("urls")
+                .and_then(|v| v.as_array())
+                .map(|urls| {
+                    urls.iter()
+                        .map(|u| {
+                            u.get("url")
+                                .and_then(|v| v.as_str())
+                                .map(|s| s.to_string())
+                                .ok_or_else(|| anyhow!("File source without url: {}", u))
+                        })
+                        .collect::<Result<Vec<_>>>()
+                })
+                .transpose()?
+                .unwrap_or_default();
+            let expires = data
+                .get("expires")
+                .and_then(Value::as_str)
+                .map(|s| s.to_string());
+            Ok((urls, expires))
         } else {
             Err(anyhow!(
-                "Failed to list files: {}",
+                "Failed to get file source: {}",
                 api_resp.msg.unwrap_or_default()
             ))
         }
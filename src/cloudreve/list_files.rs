@@ -6,16 +6,87 @@
  */
 use crate::cloudreve::{CloudreveClient, DirectoryResponse};
 use anyhow::{anyhow, Result};
+use futures::stream::{self, BoxStream, SelectAll, Stream, StreamExt, TryStreamExt};
 use log::info;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::VecDeque;
 use std::env;
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderBy {
+    #[default]
+    Name,
+    Size,
+    Created,
+    Modified,
+}
+
+impl OrderBy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderBy::Name => "name",
+            OrderBy::Size => "size",
+            OrderBy::Created => "created_at",
+            OrderBy::Modified => "updated_at",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl OrderDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderDirection::Asc => "asc",
+            OrderDirection::Desc => "desc",
+        }
+    }
+}
+
+/// Restricts a listing to one kind of entry. Anything but `All` turns the
+/// listing into a search below the folder, using Cloudreve's uri search params.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileFilter {
+    #[default]
+    All,
+    Folders,
+    Files,
+    Images,
+    Videos,
+    Audio,
+    Documents,
+}
+
+impl FileFilter {
+    fn search_param(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            FileFilter::All => None,
+            FileFilter::Folders => Some(("type", "folder")),
+            FileFilter::Files => Some(("type", "file")),
+            FileFilter::Images => Some(("category", "image")),
+            FileFilter::Videos => Some(("category", "video")),
+            FileFilter::Audio => Some(("category", "audio")),
+            FileFilter::Documents => Some(("category", "document")),
+        }
+    }
+}
+
 pub struct ListFilesBuilder<'a> {
     pub(crate) client: &'a CloudreveClient,
     pub(crate) uri: String,
     pub(crate) page: Option<u32>,
     pub(crate) page_size: Option<u32>,
     pub(crate) next_page_token: Option<String>,
+    pub(crate) order_by: Option<OrderBy>,
+    pub(crate) order_direction: Option<OrderDirection>,
+    pub(crate) filter: FileFilter,
+    pub(crate) search: Option<String>,
 }
 
 impl<'a> ListFilesBuilder<'a> {
@@ -26,6 +97,10 @@ impl<'a> ListFilesBuilder<'a> {
             page: None,
             page_size: None,
             next_page_token: None,
+            order_by: None,
+            order_direction: None,
+            filter: FileFilter::All,
+            search: None,
         }
     }
 
@@ -49,12 +124,46 @@ impl<'a> ListFilesBuilder<'a> {
         self
     }
 
+    pub fn order_by(mut self, order_by: OrderBy) -> Self {
+        self.order_by = Some(order_by);
+        self
+    }
+
+    pub fn order_direction(mut self, direction: OrderDirection) -> Self {
+        self.order_direction = Some(direction);
+        self
+    }
+
+    pub fn filter(mut self, filter: FileFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Searches below the uri for entries whose name contains `name`.
+    pub fn search(mut self, name: &str) -> Self {
+        self.search = Some(name.to_string());
+        self
+    }
+
     pub async fn send(self) -> Result<(Value, Option<String>)> {
-        let uri = if self.uri.is_empty() {
+        let mut uri = if self.uri.is_empty() {
             env::var("CLOUDEREVE_BASE_PATH").unwrap_or_else(|_| "cloudreve://my".to_string())
         } else {
             self.uri
         };
+        if let Some((key, value)) = self.filter.search_param() {
+            let separator = if uri.contains('?') { '&' } else { '?' };
+            uri = format!("{}{}{}={}", uri, separator, key, value);
+        }
+        if let Some(name) = &self.search {
+            let separator = if uri.contains('?') { '&' } else { '?' };
+            uri = format!(
+                "{}{}name={}&case_folding=true",
+                uri,
+                separator,
+                urlencoding::encode(name)
+            );
+        }
         info!("Listing files in uri: {}", uri);
         let path_url = "/file";
 
@@ -72,6 +181,8 @@ impl<'a> ListFilesBuilder<'a> {
                 &self.page_size.map_or("50".to_string(), |ps| ps.to_string()),
             )])
             .query(&[("next_page_token", &self.next_page_token.unwrap_or_default())])
+            .query(&[("order_by", self.order_by.map(|o| o.as_str()))])
+            .query(&[("order_direction", self.order_direction.map(|d| d.as_str()))])
             .send()
             .await?;
 
@@ -82,31 +193,13 @@ impl<'a> ListFilesBuilder<'a> {
 
         if api_resp.code == 0 {
             let data = api_resp.data.unwrap_or(Value::Null);
-            // Try to extract next_page_token from pagination object in data
+            // 游标分页: 没有下一页时 next_token 为空
             let next_token = data
-                .get("pagination")
-                .and_then(|p| p.get("next_page_token")) // Standard might be page_token? Check doc.
-                // Re-checking doc: "next_page_token" is query param, response usually has it in meta or similar.
-                // Assuming standard cursor pagination where it might be in pagination object.
-                // Based on previous search, it seemed to be a query param for request.
-                // Let's assume it's in data.pagination.next_page_token or similar.
-                // Or maybe just check if we have more pages?
-                // For now, let's extract it if present.
+                .pointer("/pagination/next_token")
                 .and_then(|v| v.as_str())
+                .filter(|s| !s.is_empty())
                 .map(|s| s.to_string());
 
-            // Wait, standard Cloudreve list response usually has objects.
-            // If it's cursor based, it might be in the response props.
-            // Let's return the whole data value and let the caller parse the token for now,
-            // OR extracting it here is better.
-
-            // Let's try to find it in "pagination" object inside data.
-            // data: { files: [...], pagination: { page: ..., page_size: ..., next_page_token: ...? } }
-            // If it's cursor based.
-
-            // Actually, based on my previous read of V4 docs (from memory/search):
-            // It might be data.pagination.next_page_token
-
             Ok((data, next_token))
         } else {
             Err(anyhow!(
@@ -117,8 +210,161 @@ impl<'a> ListFilesBuilder<'a> {
     }
 }
 
+// list_all 每页的条目数，以及 walk 同时列出的目录数
+const LIST_ALL_PAGE_SIZE: u32 = 100;
+const WALK_CONCURRENCY: usize = 4;
+
+/// One entry of a folder listing.
+#[derive(Clone, Debug, Deserialize)]
+pub struct FileEntry {
+    /// 0 for files, 1 for folders.
+    #[serde(rename = "type")]
+    pub file_type: i64,
+    pub name: String,
+    pub path: String,
+    #[serde(default)]
+    pub size: u64,
+}
+
+impl FileEntry {
+    pub fn is_folder(&self) -> bool {
+        self.file_type == 1
+    }
+}
+
+// 偏移分页没有 next_token，按 total_items 判断是否还有下一页
+fn next_offset_page(data: &Value, page: u32) -> Option<u32> {
+    let pagination = data.get("pagination")?;
+    if pagination.get("is_cursor").and_then(|v| v.as_bool()) == Some(true) {
+        return None;
+    }
+    let total_items = pagination.get("total_items")?.as_u64()?;
+    let page_size = pagination
+        .get("page_size")
+        .and_then(|v| v.as_u64())
+        .filter(|size| *size > 0)
+        .unwrap_or(LIST_ALL_PAGE_SIZE as u64);
+    ((page as u64 + 1) * page_size < total_items).then_some(page + 1)
+}
+
+fn page_entries(data: &Value) -> Result<Vec<FileEntry>> {
+    match data.get("files") {
+        Some(files) => serde_json::from_value(files.clone())
+            .map_err(|e| anyhow!("Failed to parse file entries: {}", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
 impl CloudreveClient {
     pub fn list_files(&self) -> ListFilesBuilder<'_> {
         ListFilesBuilder::new(self)
     }
+
+    /// Every entry of a folder, fetching the following pages as the stream is consumed.
+    pub fn list_all<'a>(&'a self, uri: &str) -> impl Stream<Item = Result<FileEntry>> + 'a {
+        self.list_all_filtered(uri, FileFilter::All)
+    }
+
+    /// Like `list_all`, but only the entries matching `filter`, as in a filtered listing.
+    pub fn list_all_filtered<'a>(
+        &'a self,
+        uri: &str,
+        filter: FileFilter,
+    ) -> impl Stream<Item = Result<FileEntry>> + 'a {
+        let uri = uri.to_string();
+        // (页码, 游标)，None 表示已经没有下一页
+        stream::try_unfold(Some((0, String::new())), move |cursor| {
+            let uri = uri.clone();
+            async move {
+                let Some((page, token)) = cursor else {
+                    return Ok::<_, anyhow::Error>(None);
+                };
+                let (data, next_token) = self
+                    .list_files()
+                    .uri(&uri)
+                    .page(page)
+                    .page_size(LIST_ALL_PAGE_SIZE)
+                    .filter(filter)
+                    .next_page_token(&token)
+                    .send()
+                    .await?;
+                let entries = page_entries(&data)?;
+                let next = match next_token {
+                    Some(token) => Some((page + 1, token)),
+                    // 空页时停止，避免 total_items 不准确时无限翻页
+                    None if !entries.is_empty() => {
+                        next_offset_page(&data, page).map(|page| (page, String::new()))
+                    }
+                    None => None,
+                };
+                Ok(Some((stream::iter(entries.into_iter().map(Ok)), next)))
+            }
+        })
+        .try_flatten()
+    }
+
+    /// Every entry below a folder, down to `max_depth` levels (1 lists only the
+    /// folder itself). Up to `WALK_CONCURRENCY` folders are listed at a time and
+    /// entries are yielded as their pages arrive. A folder that cannot be listed
+    /// yields one error naming it, and the walk goes on with the others.
+    pub fn walk<'a>(
+        &'a self,
+        uri: &str,
+        max_depth: usize,
+    ) -> impl Stream<Item = Result<FileEntry>> + 'a {
+        // 等待列出的目录及其层级，以及正在列出的目录
+        let mut queue = VecDeque::new();
+        if max_depth > 0 {
+            queue.push_back((uri.to_string(), 0));
+        }
+        let listing: SelectAll<BoxStream<'a, (usize, String, Result<FileEntry>)>> =
+            SelectAll::new();
+        stream::unfold(
+            (queue, listing),
+            move |(mut queue, mut listing)| async move {
+                while listing.len() < WALK_CONCURRENCY {
+                    let Some((folder, depth)) = queue.pop_front() else {
+                        break;
+                    };
+                    let entries = self
+                        .list_all(&folder)
+                        .map(move |entry| (depth, folder.clone(), entry));
+                    listing.push(entries.boxed());
+                }
+                let item = match listing.next().await? {
+                    (depth, _, Ok(entry)) => {
+                        if entry.is_folder() && depth + 1 < max_depth {
+                            queue.push_back((entry.path.clone(), depth + 1));
+                        }
+                        Ok(entry)
+                    }
+                    (_, folder, Err(e)) => Err(anyhow!("{}: {}", folder, e)),
+                };
+                Some((item, (queue, listing)))
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn next_offset_page_follows_total_items() {
+        let data = json!({"pagination": {"page": 0, "page_size": 100, "total_items": 250}});
+        assert_eq!(next_offset_page(&data, 0), Some(1));
+        assert_eq!(next_offset_page(&data, 1), Some(2));
+        assert_eq!(next_offset_page(&data, 2), None);
+    }
+
+    #[test]
+    fn next_offset_page_stops_without_offset_pagination() {
+        let cursor = json!({"pagination": {"is_cursor": true, "total_items": 250}});
+        assert_eq!(next_offset_page(&cursor, 0), None);
+        let no_total = json!({"pagination": {"page_size": 100}});
+        assert_eq!(next_offset_page(&no_total, 0), None);
+        assert_eq!(next_offset_page(&json!({}), 0), None);
+    }
 }
@@ -7,10 +7,21 @@ use serde_json::Value;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+mod file_info;
+mod file_manage;
 mod file_source;
+pub use file_source::{FileSource, SourceOptions};
 mod list_files;
+pub use list_files::{FileEntry, FileFilter, OrderBy, OrderDirection};
 mod remote_download;
 mod remote_list;
+pub use remote_list::task_id;
+mod share;
+mod thumbnail;
+mod upload;
+pub mod uri;
+mod user;
+mod workflow;
 
 #[derive(Default, Debug)]
 struct ClientState {
@@ -1,34 +1,22 @@
-use crate::cloudreve::{CloudreveClient, DirectoryResponse};
-use anyhow::{anyhow, Result};
+use crate::cloudreve::CloudreveClient;
+use anyhow::Result;
 use serde_json::Value;
-use std::env;
 
 impl CloudreveClient {
-    pub async fn remote_download(&self, url: &str) -> Result<Value> {
-        let dst = env::var("CLOUDEREVE_DOWNLOAD_PATH")?;
+    pub async fn remote_download(&self, urls: &[String], dst: &str) -> Result<Value> {
         let body = serde_json::json!({
             "dst": dst,
-            "src": [&url]
+            "src": urls
         });
-        let resp = self
-            .request_builder(reqwest::Method::POST, "/workflow/download")
-            .await
-            .json(&body)
-            .send()
-            .await?;
-
-        let text = resp.text().await?;
-
-        let api_resp: DirectoryResponse = serde_json::from_str(&text)
-            .map_err(|e| anyhow!("Failed to parse directory response: {} - {}", e, text))?;
+        self.create_workflow_task("/workflow/download", &body).await
+    }
 
-        if api_resp.code == 0 {
-            Ok(api_resp.data.unwrap_or_default())
-        } else {
-            Err(anyhow!(
-                "Failed to download file: {}",
-                api_resp.msg.unwrap_or_default()
-            ))
-        }
+    // 以云盘内已上传的种子文件创建离线下载任务
+    pub async fn remote_download_torrent(&self, torrent_uri: &str, dst: &str) -> Result<Value> {
+        let body = serde_json::json!({
+            "dst": dst,
+            "src_file": torrent_uri
+        });
+        self.create_workflow_task("/workflow/download", &body).await
     }
 }
@@ -1,5 +1,3 @@
-use std::{thread::sleep, time::Duration};
-
 /*
  * @Author: taro etsy@live.com
  * @LastEditors: taro etsy@live.com
@@ -9,10 +7,15 @@ use std::{thread::sleep, time::Duration};
 use crate::cloudreve::{CloudreveClient, DirectoryResponse};
 use anyhow::{anyhow, Result};
 use log::info;
-use serde_json::Value;
+use serde_json::{Map, Value};
+use std::time::Duration;
+use tokio::time::sleep;
+
+// 任务尚无下载信息时的重试次数和间隔
+const SEARCH_RETRIES: u32 = 3;
+const SEARCH_RETRY_INTERVAL: Duration = Duration::from_secs(5);
 
 // 搜索JSON对象中的指定键值对
-#[allow(dead_code)]
 pub fn object_array_search(array: &[Value], key: &str, target_value: &str) -> Option<Value> {
     for item in array {
         if let Some(item_obj) = item.as_object() {
@@ -28,6 +31,25 @@ pub fn object_array_search(array: &[Value], key: &str, target_value: &str) -> Op
     }
     None
 }
+// 任务的下载来源；种子文件任务没有 src_str，以其云盘 uri (src) 代替
+fn task_src(task: &Value) -> Option<&str> {
+    let props = task.get("summary")?.get("props")?;
+    props
+        .get("src_str")
+        .and_then(|s| s.as_str())
+        .filter(|s| !s.is_empty())
+        .or_else(|| props.get("src").and_then(|s| s.as_str()))
+}
+
+// 任务 id 可能是字符串或数字
+pub fn task_id(task: &Value) -> Option<String> {
+    match task.get("id")? {
+        Value::String(id) => Some(id.clone()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
 impl CloudreveClient {
     pub async fn remote_list(&self, category: &str) -> Result<Value> {
         let resp = self
@@ -52,83 +74,93 @@ impl CloudreveClient {
         }
     }
 
+    // 按下载来源查找任务，返回完整的任务对象
+    pub async fn find_task_by_src(&self, category: &str, url: &str) -> Result<Option<Value>> {
+        let data = self.remote_list(category).await?;
+        let task = data
+            .get("tasks")
+            .and_then(|t| t.as_array())
+            .and_then(|tasks| tasks.iter().find(|task| task_src(task) == Some(url)))
+            .cloned();
+        Ok(task)
+    }
+
+    // 按任务 id 查找任务，用于跟踪压缩、解压等后台任务
+    pub async fn find_task_by_id(&self, category: &str, id: &str) -> Result<Option<Value>> {
+        let data = self.remote_list(category).await?;
+        let task = data
+            .get("tasks")
+            .and_then(|t| t.as_array())
+            .and_then(|tasks| {
+                tasks
+                    .iter()
+                    .find(|task| task_id(task).as_deref() == Some(id))
+            })
+            .cloned();
+        Ok(task)
+    }
+
+    // 任务已创建但尚未开始下载时没有 download 信息，稍后重试
     pub async fn search_remote_list_by_url(&self, category: &str, url: &str) -> Result<Value> {
-        // 使用 Box::pin 包装递归调用以避免无限大的 Future
-        Box::pin(async move {
-            if url.is_empty() || category.is_empty() {
-                return Err(anyhow!("URL or category is empty"));
+        if url.is_empty() || category.is_empty() {
+            return Err(anyhow!("URL or category is empty"));
+        }
+        for attempt in 0..=SEARCH_RETRIES {
+            if attempt > 0 {
+                sleep(SEARCH_RETRY_INTERVAL).await;
             }
             let data = self.remote_list(category).await?;
+            let task = data
+                .get("tasks")
+                .and_then(|t| t.as_array())
+                .and_then(|tasks| tasks.iter().find(|task| task_src(task) == Some(url)));
+            let Some(task) = task else {
+                return Err(anyhow!("Task not found"));
+            };
+            info!("Found task: {:?}", url);
+            let download = task
+                .get("summary")
+                .and_then(|s| s.get("props"))
+                .and_then(|p| p.get("download"))
+                .and_then(|d| d.as_object());
+            if let Some(download) = download {
+                return Ok(download_summary(download));
+            }
+        }
+        Err(anyhow!("Task has not started downloading"))
+    }
+}
 
-            if let Some(tasks) = data.get("tasks").and_then(|t| t.as_array()) {
-                for task in tasks {
-                    if let Some(summary) = task.get("summary").and_then(|s| s.as_object()) {
-                        if let Some(props) = summary.get("props").and_then(|p| p.as_object()) {
-                            if let Some(src_str) = props.get("src_str").and_then(|s| s.as_str()) {
-                                if src_str == url {
-                                    info!("Found task: {:?}", url);
-                                    if let Some(download) =
-                                        props.get("download").and_then(|d| d.as_object())
-                                    {
-                                        let name = download
-                                            .get("name")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or_default();
-                                        let size = download
-                                            .get("size")
-                                            .and_then(|v| v.as_u64())
-                                            .unwrap_or_default();
-                                        let empty_files = Vec::new();
-                                        let files = download
-                                            .get("files")
-                                            .and_then(|v| v.as_array())
-                                            .unwrap_or(&empty_files);
-                                        let size_str =
-                                            format!("{:.2} MB", size as f64 / 1024.0 / 1024.0);
-                                        let progress = object_array_search(files, "name", name)
-                                            .and_then(|v| v.get("progress").cloned());
-
-                                        let progress_val =
-                                            progress.and_then(|v| v.as_f64()).unwrap_or(0.0);
-                                        let val = progress_val * 100.0;
-                                        let progress_str = if (val - 100.0).abs() < f64::EPSILON {
-                                            "100".to_string()
-                                        } else {
-                                            format!("{:.2}", val)
-                                        };
-
-                                        let mut download_obj = download.clone();
-                                        download_obj.insert(
-                                            "name".to_string(),
-                                            serde_json::Value::String(name.to_string()),
-                                        );
-                                        download_obj.insert(
-                                            "size".to_string(),
-                                            serde_json::Value::String(size_str),
-                                        );
-                                        download_obj.insert(
-                                            "progress".to_string(),
-                                            serde_json::Value::String(progress_str),
-                                        );
+// 下载信息中的文件名、格式化后的大小和进度
+fn download_summary(download: &Map<String, Value>) -> Value {
+    let name = download
+        .get("name")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    let size = download
+        .get("size")
+        .and_then(|v| v.as_u64())
+        .unwrap_or_default();
+    let empty_files = Vec::new();
+    let files = download
+        .get("files")
+        .and_then(|v| v.as_array())
+        .unwrap_or(&empty_files);
+    let size_str = format!("{:.2} MB", size as f64 / 1024.0 / 1024.0);
+    let progress =
+        object_array_search(files, "name", name).and_then(|v| v.get("progress").cloned());
 
-                                        return Ok(serde_json::Value::Object(download_obj));
-                                    } else {
-                                        sleep(Duration::from_secs(5));
-                                        // 递归调用时再次使用 Box::pin
-                                        let download_obj =
-                                            Box::pin(self.search_remote_list_by_url(category, url))
-                                                .await?;
-                                        return Ok(download_obj);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+    let progress_val = progress.and_then(|v| v.as_f64()).unwrap_or(0.0);
+    let val = progress_val * 100.0;
+    let progress_str = if (val - 100.0).abs() < f64::EPSILON {
+        "100".to_string()
+    } else {
+        format!("{:.2}", val)
+    };
 
-            Err(anyhow!("Task not found"))
-        })
-        .await
-    }
+    let mut download_obj = download.clone();
+    download_obj.insert("name".to_string(), Value::String(name.to_string()));
+    download_obj.insert("size".to_string(), Value::String(size_str));
+    download_obj.insert("progress".to_string(), Value::String(progress_str));
+    Value::Object(download_obj)
 }
@@ -0,0 +1,38 @@
+use crate::cloudreve::{CloudreveClient, DirectoryResponse};
+use anyhow::{anyhow, Result};
+
+impl CloudreveClient {
+    // 创建公开分享，返回分享链接
+    pub async fn create_share_link(&self, uri: &str) -> Result<String> {
+        let body = serde_json::json!({
+            "uri": uri,
+            "is_private": false,
+        });
+
+        let resp = self
+            .request_builder(reqwest::Method::PUT, "/share")
+            .await
+            .json(&body)
+            .send()
+            .await?;
+
+        let text = resp.text().await?;
+
+        let api_resp: DirectoryResponse = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("Failed to parse share response: {} - {}", e, text))?;
+
+        if api_resp.code == 0 {
+            api_resp
+                .data
+                .as_ref()
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+                .ok_or_else(|| anyhow!("Share link missing"))
+        } else {
+            Err(anyhow!(
+                "Failed to create share link: {}",
+                api_resp.msg.unwrap_or_default()
+            ))
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use crate::cloudreve::{CloudreveClient, DirectoryResponse};
+use anyhow::{anyhow, Result};
+
+impl CloudreveClient {
+    // 获取缩略图地址，没有缩略图的文件会返回错误
+    pub async fn get_thumbnail(&self, uri: &str) -> Result<String> {
+        let resp = self
+            .request_builder(reqwest::Method::GET, "/file/thumb")
+            .await
+            .query(&[("uri", uri)])
+            .send()
+            .await?;
+
+        let text = resp.text().await?;
+
+        let api_resp: DirectoryResponse = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("Failed to parse thumbnail response: {} - {}", e, text))?;
+
+        if api_resp.code == 0 {
+            api_resp
+                .data
+                .as_ref()
+                .and_then(|data| data.get("url"))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+                .ok_or_else(|| anyhow!("Thumbnail url missing"))
+        } else {
+            Err(anyhow!(
+                "Failed to get thumbnail: {}",
+                api_resp.msg.unwrap_or_default()
+            ))
+        }
+    }
+
+    // 下载缩略图等签名地址的内容
+    pub async fn download_content(&self, url: &str) -> Result<Vec<u8>> {
+        let resp = self.client.get(url).send().await?.error_for_status()?;
+        Ok(resp.bytes().await?.to_vec())
+    }
+}
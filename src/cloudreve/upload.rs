@@ -0,0 +1,122 @@
+use crate::cloudreve::{CloudreveClient, DirectoryResponse};
+use anyhow::{anyhow, Result};
+use log::{error, info};
+use serde_json::Value;
+
+impl CloudreveClient {
+    // 上传文件到指定 uri，目前仅支持本机存储策略；失败时删除上传会话及占位文件
+    pub async fn upload_file(&self, uri: &str, content: Vec<u8>) -> Result<()> {
+        let body = serde_json::json!({
+            "uri": uri,
+            "size": content.len(),
+        });
+
+        let resp = self
+            .request_builder(reqwest::Method::PUT, "/file/upload")
+            .await
+            .json(&body)
+            .send()
+            .await?;
+
+        let text = resp.text().await?;
+
+        let api_resp: DirectoryResponse = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("Failed to parse upload session response: {} - {}", e, text))?;
+
+        if api_resp.code != 0 {
+            return Err(anyhow!(
+                "Failed to create upload session: {}",
+                api_resp.msg.unwrap_or_default()
+            ));
+        }
+
+        let session = api_resp.data.unwrap_or(Value::Null);
+        let session_id = session
+            .get("session_id")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Upload session id missing"))?;
+        let policy_type = session
+            .get("storage_policy")
+            .and_then(|p| p.get("type"))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        let uploaded = if policy_type == "local" {
+            let chunk_size = session
+                .get("chunk_size")
+                .and_then(|v| v.as_u64())
+                .filter(|size| *size > 0)
+                .map_or(content.len().max(1), |size| size as usize);
+            info!("Uploading {} bytes to {}", content.len(), uri);
+            self.upload_chunks(session_id, chunk_size, &content).await
+        } else {
+            Err(anyhow!("Unsupported storage policy: {}", policy_type))
+        };
+        if uploaded.is_err() {
+            if let Err(e) = self.delete_upload_session(session_id, uri).await {
+                error!("Failed to delete upload session {}: {}", session_id, e);
+            }
+        }
+        uploaded
+    }
+
+    async fn upload_chunks(
+        &self,
+        session_id: &str,
+        chunk_size: usize,
+        content: &[u8],
+    ) -> Result<()> {
+        for (index, chunk) in content.chunks(chunk_size).enumerate() {
+            let resp = self
+                .request_builder(
+                    reqwest::Method::POST,
+                    &format!("/file/upload/{}/{}", session_id, index),
+                )
+                .await
+                .header("Content-Type", "application/octet-stream")
+                .body(chunk.to_vec())
+                .send()
+                .await?;
+
+            let text = resp.text().await?;
+
+            let api_resp: DirectoryResponse = serde_json::from_str(&text)
+                .map_err(|e| anyhow!("Failed to parse upload response: {} - {}", e, text))?;
+
+            if api_resp.code != 0 {
+                return Err(anyhow!(
+                    "Failed to upload chunk {}: {}",
+                    index,
+                    api_resp.msg.unwrap_or_default()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_upload_session(&self, session_id: &str, uri: &str) -> Result<()> {
+        let body = serde_json::json!({
+            "id": session_id,
+            "uri": uri,
+        });
+        let resp = self
+            .request_builder(reqwest::Method::DELETE, "/file/upload")
+            .await
+            .json(&body)
+            .send()
+            .await?;
+
+        let text = resp.text().await?;
+
+        let api_resp: DirectoryResponse = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("Failed to parse delete session response: {} - {}", e, text))?;
+
+        if api_resp.code == 0 {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Failed to delete upload session: {}",
+                api_resp.msg.unwrap_or_default()
+            ))
+        }
+    }
+}
@@ -0,0 +1,207 @@
+// Helpers for Cloudreve file uris such as `cloudreve://my/Movies/2024`.
+// Path segments are kept percent-encoded, the same way the API returns them.
+
+/// Root of the recycle bin.
+pub const TRASH: &str = "cloudreve://trash";
+
+/// Splits a uri into its root (`cloudreve://my`) and path segments, dropping any query.
+pub fn split(uri: &str) -> (&str, Vec<&str>) {
+    let uri = uri.split('?').next().unwrap_or(uri);
+    let path_start = uri
+        .find("://")
+        .map(|scheme_end| scheme_end + 3)
+        .and_then(|start| uri[start..].find('/').map(|slash| start + slash))
+        .unwrap_or(uri.len());
+    let segments = uri[path_start..]
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    (&uri[..path_start], segments)
+}
+
+pub fn root(uri: &str) -> &str {
+    split(uri).0
+}
+
+/// Parent folder of a uri, or `None` at the root.
+pub fn parent(uri: &str) -> Option<String> {
+    let (root, mut segments) = split(uri);
+    segments.pop()?;
+    Some(build(root, &segments))
+}
+
+pub fn join(folder: &str, name: &str) -> String {
+    format!(
+        "{}/{}",
+        folder.trim_end_matches('/'),
+        urlencoding::encode(name)
+    )
+}
+
+/// Decoded last segment of a uri, or the root itself.
+pub fn display_name(uri: &str) -> String {
+    let (root, segments) = split(uri);
+    match segments.last() {
+        Some(name) => decode(name),
+        None => root.to_string(),
+    }
+}
+
+pub fn decode(s: &str) -> String {
+    urlencoding::decode(s)
+        .map(|s| s.into_owned())
+        .unwrap_or_else(|_| s.to_string())
+}
+
+/// Every ancestor of a uri including itself, as `(label, uri)` pairs from the root down.
+pub fn breadcrumbs(uri: &str) -> Vec<(String, String)> {
+    let (root, segments) = split(uri);
+    let root_label = root.rsplit("://").next().unwrap_or(root).to_string();
+    let mut crumbs = vec![(root_label, root.to_string())];
+    for (i, segment) in segments.iter().enumerate() {
+        crumbs.push((decode(segment), build(root, &segments[..=i])));
+    }
+    crumbs
+}
+
+fn build(root: &str, segments: &[&str]) -> String {
+    if segments.is_empty() {
+        root.to_string()
+    } else {
+        format!("{}/{}", root, segments.join("/"))
+    }
+}
+
+/// Resolves a shell-style path against the folder `cwd`. The path may be a full uri,
+/// absolute from the root of `cwd` (`/Movies`), or relative (`..`, `./sub`, `a/b`).
+/// Segments may be percent-encoded, quoted (`"My Movies"/sub`), or escape spaces (`My\ Movies`).
+pub fn resolve(cwd: &str, path: &str) -> String {
+    let path = path.trim();
+    let (root, base, rest) = if path.contains("://") {
+        let root = split(path).0;
+        (root, Vec::new(), shell_segments(&path[root.len()..]))
+    } else {
+        let rest = shell_segments(path);
+        // 以 / 开头（可以在引号内）时第一段为空
+        if rest.len() > 1 && rest[0].is_empty() {
+            (root(cwd), Vec::new(), rest)
+        } else {
+            let (root, segments) = split(cwd);
+            (root, segments, rest)
+        }
+    };
+    let mut segments: Vec<String> = base.into_iter().map(String::from).collect();
+    for segment in rest {
+        match segment.as_str() {
+            "" | "." => {}
+            // 已在根目录时忽略多余的 ..
+            ".." => {
+                segments.pop();
+            }
+            // 先解码再统一编码，已编码和未编码的名称得到同一个 uri
+            name => segments.push(urlencoding::encode(&decode(name)).into_owned()),
+        }
+    }
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    build(root, &segments)
+}
+
+// 按 / 切分路径并去掉引号；名称中不会有 /，引号内的 / 同样是分隔符。
+// 引号内的空格等字符原样保留，引号外的反斜杠转义下一个字符
+fn shell_segments(path: &str) -> Vec<String> {
+    let mut segments = vec![String::new()];
+    let mut quote = None;
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        let segment = segments.last_mut().unwrap();
+        match (quote, c) {
+            (_, '/') => segments.push(String::new()),
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => segment.push(c),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '\\') => segment.extend(chars.next()),
+            (None, c) => segment.push(c),
+        }
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CWD: &str = "cloudreve://my/Movies/2024";
+
+    #[test]
+    fn split_drops_query_and_empty_segments() {
+        assert_eq!(
+            split("cloudreve://my/a//b/?category=image"),
+            ("cloudreve://my", vec!["a", "b"])
+        );
+        assert_eq!(split("cloudreve://my"), ("cloudreve://my", vec![]));
+    }
+
+    #[test]
+    fn parent_stops_at_root() {
+        assert_eq!(parent(CWD).as_deref(), Some("cloudreve://my/Movies"));
+        assert_eq!(
+            parent("cloudreve://my/Movies").as_deref(),
+            Some("cloudreve://my")
+        );
+        assert_eq!(parent("cloudreve://my"), None);
+    }
+
+    #[test]
+    fn breadcrumbs_list_every_ancestor() {
+        assert_eq!(
+            breadcrumbs("cloudreve://my/My%20Movies/2024"),
+            vec![
+                ("my".to_string(), "cloudreve://my".to_string()),
+                (
+                    "My Movies".to_string(),
+                    "cloudreve://my/My%20Movies".to_string()
+                ),
+                (
+                    "2024".to_string(),
+                    "cloudreve://my/My%20Movies/2024".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_relative_and_absolute_paths() {
+        assert_eq!(resolve(CWD, "sub"), "cloudreve://my/Movies/2024/sub");
+        assert_eq!(resolve(CWD, "./a/b/"), "cloudreve://my/Movies/2024/a/b");
+        assert_eq!(resolve(CWD, ".."), "cloudreve://my/Movies");
+        assert_eq!(resolve(CWD, "../../../.."), "cloudreve://my");
+        assert_eq!(resolve(CWD, "/Music"), "cloudreve://my/Music");
+        assert_eq!(resolve(CWD, "/"), "cloudreve://my");
+        assert_eq!(resolve(CWD, ""), CWD);
+        assert_eq!(
+            resolve(CWD, "cloudreve://trash/a/../b"),
+            "cloudreve://trash/b"
+        );
+    }
+
+    #[test]
+    fn resolve_encodes_names_once() {
+        assert_eq!(resolve(CWD, "/My Movies"), "cloudreve://my/My%20Movies");
+        assert_eq!(resolve(CWD, "/My%20Movies"), "cloudreve://my/My%20Movies");
+        assert_eq!(resolve(CWD, "/100%"), "cloudreve://my/100%25");
+    }
+
+    #[test]
+    fn resolve_quoted_and_escaped_segments() {
+        let expected = "cloudreve://my/My%20Movies/sub";
+        assert_eq!(resolve(CWD, "\"/My Movies/sub\""), expected);
+        assert_eq!(resolve(CWD, "'/My Movies'/sub"), expected);
+        assert_eq!(resolve(CWD, "/\"My Movies\"/sub"), expected);
+        assert_eq!(resolve(CWD, "/My\\ Movies/sub"), expected);
+        assert_eq!(
+            resolve(CWD, "\"a b\"/c"),
+            "cloudreve://my/Movies/2024/a%20b/c"
+        );
+        assert_eq!(resolve(CWD, "'it''s'"), "cloudreve://my/Movies/2024/its");
+    }
+}
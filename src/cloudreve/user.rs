@@ -0,0 +1,38 @@
+use crate::cloudreve::{CloudreveClient, DirectoryResponse};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+/// Storage quota of the logged in user, in bytes.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct Capacity {
+    #[serde(default)]
+    pub total: u64,
+    #[serde(default)]
+    pub used: u64,
+}
+
+impl CloudreveClient {
+    // 获取当前用户的存储容量和已用空间
+    pub async fn capacity(&self) -> Result<Capacity> {
+        let resp = self
+            .request_builder(reqwest::Method::GET, "/user/capacity")
+            .await
+            .send()
+            .await?;
+
+        let text = resp.text().await?;
+
+        let api_resp: DirectoryResponse = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("Failed to parse capacity response: {} - {}", e, text))?;
+
+        if api_resp.code == 0 {
+            serde_json::from_value(api_resp.data.unwrap_or_default())
+                .map_err(|e| anyhow!("Failed to parse capacity: {}", e))
+        } else {
+            Err(anyhow!(
+                "Failed to get capacity: {}",
+                api_resp.msg.unwrap_or_default()
+            ))
+        }
+    }
+}
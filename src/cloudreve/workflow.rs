@@ -0,0 +1,59 @@
+use crate::cloudreve::{CloudreveClient, DirectoryResponse};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+impl CloudreveClient {
+    // 把多个文件或目录压缩为 dst (完整的压缩包 uri)
+    pub async fn create_archive(&self, uris: &[String], dst: &str) -> Result<Value> {
+        let body = serde_json::json!({
+            "src": uris,
+            "dst": dst,
+        });
+        self.create_workflow_task("/workflow/archive", &body).await
+    }
+
+    // 解压到目录 dst；encoding 用于非 UTF-8 文件名的 zip，password 用于加密压缩包
+    pub async fn extract_archive(
+        &self,
+        uri: &str,
+        dst: &str,
+        encoding: Option<&str>,
+        password: Option<&str>,
+    ) -> Result<Value> {
+        let mut body = serde_json::json!({
+            "src": [uri],
+            "dst": dst,
+        });
+        if let Some(encoding) = encoding {
+            body["encoding"] = Value::from(encoding);
+        }
+        if let Some(password) = password {
+            body["password"] = Value::from(password);
+        }
+        self.create_workflow_task("/workflow/extract", &body).await
+    }
+
+    // 创建后台任务，返回任务对象
+    pub(crate) async fn create_workflow_task(&self, path: &str, body: &Value) -> Result<Value> {
+        let resp = self
+            .request_builder(reqwest::Method::POST, path)
+            .await
+            .json(body)
+            .send()
+            .await?;
+
+        let text = resp.text().await?;
+
+        let api_resp: DirectoryResponse = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("Failed to parse workflow response: {} - {}", e, text))?;
+
+        if api_resp.code == 0 {
+            Ok(api_resp.data.unwrap_or_default())
+        } else {
+            Err(anyhow!(
+                "Failed to create task: {}",
+                api_resp.msg.unwrap_or_default()
+            ))
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use serde::{Deserialize, Serialize};
+
+/// Language of the bot's replies, chosen per user.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    #[default]
+    Zh,
+    En,
+}
+
+impl Lang {
+    /// Language from a Telegram `language_code`; users without one keep the default.
+    pub fn from_code(code: Option<&str>) -> Self {
+        match code {
+            Some(code) if !code.starts_with("zh") => Lang::En,
+            _ => Lang::Zh,
+        }
+    }
+
+    /// Parses the argument of `/lang`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "zh" | "cn" | "中文" => Some(Lang::Zh),
+            "en" | "english" => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Lang::Zh => "zh",
+            Lang::En => "en",
+        }
+    }
+}
+
+macro_rules! messages {
+    ($($key:ident { zh: $zh:literal, en: $en:literal })*) => {
+        /// Every user-visible message; `{}` placeholders are filled in order by `tr!`.
+        #[derive(Clone, Copy, Debug)]
+        pub enum Msg {
+            $($key,)*
+        }
+
+        impl Msg {
+            pub fn text(self, lang: Lang) -> &'static str {
+                match (self, lang) {
+                    $(
+                        (Msg::$key, Lang::Zh) => $zh,
+                        (Msg::$key, Lang::En) => $en,
+                    )*
+                }
+            }
+        }
+    };
+}
+
+messages! {
+    Unknown { zh: "未知", en: "Unknown" }
+    Me { zh: "我", en: "me" }
+    Canceled { zh: "已取消", en: "Canceled" }
+
+    // 语言
+    LangCurrent { zh: "当前语言: 中文\n选择语言：", en: "Current language: English\nChoose a language:" }
+    LangSet { zh: "已切换为中文", en: "Language set to English" }
+    LangUnsupported { zh: "不支持的语言: {}，可选 en、zh", en: "Unsupported language: {}, use en or zh" }
+
+    // 设置
+    SettingsText { zh: "⚙️ <b>设置</b>\n浏览根目录: <code>{}</code>\n下载目录: <code>{}</code>\n语言按钮只改变你自己的语言，其余设置对整个聊天生效", en: "⚙️ <b>Settings</b>\nBrowse root: <code>{}</code>\nDownload folder: <code>{}</code>\nThe language button only changes your own language; the other settings apply to the whole chat" }
+    SettingOn { zh: "开", en: "on" }
+    SettingOff { zh: "关", en: "off" }
+    RootSet { zh: "已将浏览根目录设为 <code>{}</code>", en: "Browse root set to <code>{}</code>" }
+    DownloadDestinationSet { zh: "已将下载目录设为 <code>{}</code>", en: "Download folder set to <code>{}</code>" }
+    BtnPageSize { zh: "每页 {} 项", en: "{} per page" }
+    BtnLanguage { zh: "🌐 我的语言: 中文", en: "🌐 My language: English" }
+    BtnNotify { zh: "🔔 提醒: {}", en: "🔔 Notify: {}" }
+    BtnAutoOffer { zh: "🔗 链接下载: {}", en: "🔗 Offer links: {}" }
+    BtnBrowseRoot { zh: "🏠 浏览根目录", en: "🏠 Browse root" }
+    BtnDownloadDestination { zh: "📥 下载目录", en: "📥 Download folder" }
+    BtnResetSettings { zh: "♻️ 恢复默认", en: "♻️ Reset" }
+
+    // 按钮
+    BtnOpenFolder { zh: "📂 打开目录", en: "📂 Open folder" }
+    BtnGetLink { zh: "🔗 获取链接", en: "🔗 Get link" }
+    BtnShare { zh: "📤 分享", en: "📤 Share" }
+    BtnSendToChat { zh: "📨 发送到聊天", en: "📨 Send to chat" }
+    BtnRename { zh: "✏️ 重命名", en: "✏️ Rename" }
+    BtnMove { zh: "🚚 移动", en: "🚚 Move" }
+    BtnCopy { zh: "📋 复制", en: "📋 Copy" }
+    BtnDelete { zh: "🗑 删除", en: "🗑 Delete" }
+    BtnZip { zh: "📦 压缩", en: "📦 Zip" }
+    BtnExtract { zh: "📦 解压", en: "📦 Extract" }
+    BtnLinksFile { zh: "🔗 链接文件", en: "🔗 Links file" }
+    BtnDone { zh: "✖️ 完成", en: "✖️ Done" }
+    BtnRestore { zh: "♻️ 恢复", en: "♻️ Restore" }
+    BtnPurge { zh: "🔥 彻底删除", en: "🔥 Delete forever" }
+    BtnCancel { zh: "取消", en: "Cancel" }
+    BtnConfirmDelete { zh: "⚠️ 确认删除", en: "⚠️ Confirm delete" }
+    BtnConfirmDeleteCount { zh: "⚠️ 确认删除 {} 项", en: "⚠️ Delete {} items" }
+    BtnConfirmPurge { zh: "⚠️ 确认彻底删除", en: "⚠️ Confirm delete forever" }
+    BtnConfirmEmptyTrash { zh: "⚠️ 确认清空回收站", en: "⚠️ Confirm empty trash" }
+    BtnRefreshLink { zh: "🔄 刷新链接", en: "🔄 Refresh link" }
+    BtnRecount { zh: "🔄 重新统计", en: "🔄 Recount" }
+    BtnSubmitDownload { zh: "提交云盘下载", en: "Submit cloud download" }
+    BtnChooseFolder { zh: "📁 选择目录", en: "📁 Choose folder" }
+    BtnParent { zh: "⬆️ 上一级", en: "⬆️ Parent" }
+    BtnRoot { zh: "🏠 根目录", en: "🏠 Root" }
+    BtnPrev { zh: "⬅️ 上一页", en: "⬅️ Prev" }
+    BtnNext { zh: "下一页 ➡️", en: "Next ➡️" }
+    BtnSelect { zh: "☑️ 选择", en: "☑️ Select" }
+    BtnPreview { zh: "🖼 预览", en: "🖼 Preview" }
+    BtnExportLinks { zh: "📋 导出链接", en: "📋 Export links" }
+    BtnStats { zh: "📊 统计", en: "📊 Stats" }
+    BtnExportText { zh: "📄 纯文本", en: "📄 TXT" }
+    BtnExportAria2 { zh: "⚙️ aria2", en: "⚙️ aria2" }
+    BtnEmptyTrash { zh: "🧹 清空回收站", en: "🧹 Empty trash" }
+    BtnSaveHere { zh: "✅ 保存到这里", en: "✅ Save here" }
+    BtnCancelPick { zh: "❌ 取消", en: "❌ Cancel" }
+
+    // 排序和筛选
+    SortButton { zh: "排序: {}", en: "Sort: {}" }
+    FilterButton { zh: "筛选: {}", en: "Filter: {}" }
+    SortAsc { zh: "⬆️ 升序", en: "⬆️ Asc" }
+    SortDesc { zh: "⬇️ 降序", en: "⬇️ Desc" }
+    OrderName { zh: "名称", en: "Name" }
+    OrderSize { zh: "大小", en: "Size" }
+    OrderCreated { zh: "创建时间", en: "Created" }
+    OrderModified { zh: "修改时间", en: "Modified" }
+    FilterAll { zh: "全部", en: "All" }
+    FilterFolders { zh: "目录", en: "Folders" }
+    FilterFiles { zh: "文件", en: "Files" }
+    FilterImages { zh: "图片", en: "Images" }
+    FilterVideos { zh: "视频", en: "Videos" }
+    FilterAudio { zh: "音频", en: "Audio" }
+    FilterDocuments { zh: "文档", en: "Documents" }
+
+    // 文件列表
+    ListHeader { zh: "`{}` 中的文件（第 {} 页）：", en: "Files in `{}` (Page {}):" }
+    PickHeader { zh: "选择保存目录 `{}`（第 {} 页）：", en: "Choose destination `{}` (Page {}):" }
+    SelectHeader { zh: "选择 `{}` 中的文件（第 {} 页）：\n已选择 {} 项", en: "Select files in `{}` (Page {}):\n{} selected" }
+    TrashHeader { zh: "🗑 回收站（第 {} 页）：", en: "🗑 Trash (Page {}):" }
+    FolderTotals { zh: "\n共 {} 个目录, {} 个文件, {}", en: "\n{} folders, {} files, {} in total" }
+    DirectoryEmpty { zh: "目录 `{}` 是空的。", en: "Directory `{}` is empty." }
+    TrashEmpty { zh: "🗑 回收站是空的。", en: "🗑 Trash is empty." }
+    ListFailed { zh: "获取文件列表失败: {}", en: "Error listing files: {}" }
+    ParseListFailed { zh: "无法解析文件列表。", en: "Failed to parse file list." }
+    NoThumbnails { zh: "本页没有缩略图。", en: "No thumbnails on this page." }
+
+    // 文件详情
+    FileDetails { zh: "<b>{}</b>\n大小: {}\n类型: {}\n创建时间: {}\n修改时间: {}\n所有者: {}\n存储策略: {}\n分享: {}", en: "<b>{}</b>\nSize: {}\nType: {}\nCreated: {}\nModified: {}\nOwner: {}\nStorage policy: {}\nSharing: {}" }
+    SharedCount { zh: "已分享 ({})", en: "Shared ({})" }
+    Shared { zh: "已分享", en: "Shared" }
+    NotShared { zh: "未分享", en: "Not shared" }
+    RestoreLocation { zh: "\n原位置: <code>{}</code>", en: "\nOriginal location: <code>{}</code>" }
+    AutoPurge { zh: "\n自动清除: {}", en: "\nPurged automatically at: {}" }
+    GetFileInfoFailed { zh: "获取文件信息失败: {}", en: "Failed to get file info: {}" }
+    GetFileDetailsFailed { zh: "获取文件详情失败: {}", en: "Failed to get file details: {}" }
+
+    // 文件操作
+    RenamePrompt { zh: "请回复新的文件名（当前：{}）", en: "Reply with the new file name (current: {})" }
+    InvalidFileName { zh: "无效的文件名", en: "Invalid file name" }
+    Renamed { zh: "已重命名为 {}", en: "Renamed to {}" }
+    RenameFailed { zh: "重命名失败: {}", en: "Rename failed: {}" }
+    Deleted { zh: "🗑 已删除 {}", en: "🗑 Deleted {}" }
+    DeletedCount { zh: "🗑 已删除 {} 项", en: "🗑 Deleted {} items" }
+    DeleteFailed { zh: "删除失败: {}", en: "Delete failed: {}" }
+    Restored { zh: "♻️ 已恢复 {}", en: "♻️ Restored {}" }
+    RestoreFailed { zh: "恢复失败: {}", en: "Restore failed: {}" }
+    Purged { zh: "🔥 已彻底删除 {}", en: "🔥 Deleted {} forever" }
+    TrashEmptied { zh: "🧹 回收站已清空，删除了 {} 项", en: "🧹 Trash emptied, {} items deleted" }
+    EmptyTrashFailed { zh: "清空回收站失败: {}", en: "Failed to empty trash: {}" }
+    FilesMoved { zh: "已移动 {} 个文件到 <code>{}</code>", en: "Moved {} files to <code>{}</code>" }
+    FilesCopied { zh: "已复制 {} 个文件到 <code>{}</code>", en: "Copied {} files to <code>{}</code>" }
+    OperationFailed { zh: "操作失败: {}", en: "Operation failed: {}" }
+    SelectFilesFirst { zh: "请先选择文件", en: "Select some files first" }
+    ShareLink { zh: "分享链接：\n<code>{}</code>", en: "Share link:\n<code>{}</code>" }
+    CreateShareFailed { zh: "创建分享失败: {}", en: "Failed to create share: {}" }
+    SendFileFailed { zh: "发送文件失败: {}", en: "Failed to send file: {}" }
+    SendFileTooLarge { zh: "文件大小 {} 超过 Bot API 的 50MB 发送限制", en: "File size {} exceeds the 50MB upload limit of the Bot API" }
+
+    // 直链
+    LinkText { zh: "单击下面的链接可直接复制：\n<code>{}</code>", en: "Tap the link below to copy it:\n<code>{}</code>" }
+    LinkExpiresAt { zh: "\n有效期至: {}", en: "\nExpires at: {}" }
+    LinksExpireAt { zh: "\n链接有效期至 {}", en: "\nLinks expire at {}" }
+    GetLinkFailed { zh: "获取下载链接失败: {}", en: "Failed to get download link: {}" }
+    GetLinksFailed { zh: "获取直链失败: {}", en: "Failed to get direct links: {}" }
+    LinksSummary { zh: "共 {} 个链接", en: "{} links" }
+    LinksFailedFiles { zh: "\n获取失败: {}", en: "\nFailed: {}" }
+    NoLinkableFiles { zh: "没有可获取链接的文件（目录没有直链）", en: "No files to link (folders have no direct link)" }
+    ExportChooseFormat { zh: "导出 {} 下所有文件的直链，选择格式：", en: "Export the direct links of every file below {}, choose a format:" }
+    ExportWalking { zh: "正在遍历目录...", en: "Walking the folder..." }
+    ExportWalkFailed { zh: "遍历目录失败: {}", en: "Failed to walk the folder: {}" }
+    ExportNoFiles { zh: "目录下没有文件", en: "No files in this folder" }
+    ExportFetching { zh: "正在获取 {} 个文件的直链...", en: "Getting the direct links of {} files..." }
+    ExportSummary { zh: "共 {} 个文件，总大小 {}", en: "{} files, {} in total" }
+    ExportFailedCount { zh: "，{} 个获取失败", en: ", {} failed" }
+    Exported { zh: "✅ 已导出 {} 的直链", en: "✅ Exported the direct links of {}" }
+
+    // 目录统计
+    Stats { zh: "📊 <b>{}</b>\n文件: {}\n子目录: {}\n总大小: {}", en: "📊 <b>{}</b>\nFiles: {}\nSubfolders: {}\nTotal size: {}" }
+    StatsByType { zh: "\n{} {} 个, {}", en: "\n{} {} files, {}" }
+    StatsCounting { zh: "📊 正在统计...", en: "📊 Counting..." }
+    StatsProgress { zh: "📊 正在统计...\n已发现 {} 个文件, {} 个目录, {}", en: "📊 Counting...\nFound {} files, {} folders, {}" }
+    StatsIncomplete { zh: "\n⚠️ {} 个目录读取失败，结果不完整：\n{}", en: "\n⚠️ {} folders could not be read, the totals are incomplete:\n{}" }
+
+    // 命令
+    CmdStart { zh: "开始使用", en: "Get started" }
+    CmdHelp { zh: "查看命令列表", en: "Show the list of commands" }
+    CmdLs { zh: "列出当前或指定目录的文件", en: "List files in the current or given folder" }
+    CmdCd { zh: "切换当前目录", en: "Change the current folder" }
+    CmdPwd { zh: "显示当前目录", en: "Show the current folder" }
+    CmdGet { zh: "获取文件的直链", en: "Get the direct link of a file" }
+    CmdTrash { zh: "浏览回收站", en: "Browse the recycle bin" }
+    CmdTasks { zh: "查看正在进行的下载", en: "Show downloads in progress" }
+    CmdQuota { zh: "查看存储空间", en: "Show storage usage" }
+    CmdLang { zh: "切换语言 (en, zh)", en: "Switch the language (en, zh)" }
+    CmdSettings { zh: "修改本会话的设置", en: "Change the settings of this chat" }
+    Welcome { zh: "👋 你好！我可以浏览和管理 Cloudreve 云盘，也可以把链接、磁力链接、种子和文件保存到云盘。\n\n{}", en: "👋 Hi! I can browse and manage your Cloudreve drive, and save links, magnet links, torrents and files to it.\n\n{}" }
+    HelpText { zh: "可用命令：\n{}\n\n直接发送链接、磁力链接、种子或文件即可提交下载。", en: "Commands:\n{}\n\nSend a link, magnet link, torrent or file to download it." }
+    AdminOnly { zh: "仅管理员可用", en: "Admins only" }
+    Cwd { zh: "📂 当前目录: <code>{}</code>", en: "📂 Current folder: <code>{}</code>" }
+    NotAFolder { zh: "不是目录: <code>{}</code>", en: "Not a folder: <code>{}</code>" }
+    OpenFolderFailed { zh: "无法打开 <code>{}</code>: {}", en: "Cannot open <code>{}</code>: {}" }
+    GetUsage { zh: "用法: /get <路径>", en: "Usage: /get <path>" }
+    Tasks { zh: "📥 正在下载 ({})：\n\n{}", en: "📥 Downloading ({}):\n\n{}" }
+    TaskLine { zh: "<b>{}</b>\n{} / {} · {}%", en: "<b>{}</b>\n{} / {} · {}%" }
+    NoTasks { zh: "没有正在进行的下载", en: "No downloads in progress" }
+    TasksFailed { zh: "获取任务列表失败: {}", en: "Failed to list tasks: {}" }
+    Quota { zh: "💾 存储空间\n已用: {} / {} ({}%)\n剩余: {}", en: "💾 Storage\nUsed: {} / {} ({}%)\nFree: {}" }
+    QuotaFailed { zh: "获取存储空间失败: {}", en: "Failed to get storage usage: {}" }
+
+    // 远程下载
+    LinkResolveFailed { zh: "解析链接失败: {}", en: "Failed to resolve link: {}" }
+    LinksResolved { zh: "解析到 {}/{} 个链接：\n{}", en: "Resolved {}/{} links:\n{}" }
+    MagnetDetected { zh: "检测到磁力链接：\n<b>{}</b>\n<code>{}</code>", en: "Magnet link detected:\n<b>{}</b>\n<code>{}</code>" }
+    TorrentDetected { zh: "检测到种子文件：\n<b>{}</b>", en: "Torrent file detected:\n<b>{}</b>" }
+    UrlInfo { zh: "<b>{}</b>\n文件大小: {}\n文件类型: {}\n<code>{}</code>", en: "<b>{}</b>\nSize: {}\nType: {}\n<code>{}</code>" }
+    FileDetected { zh: "检测到文件：\n<b>{}</b>\n文件大小: {}", en: "File detected:\n<b>{}</b>\nSize: {}" }
+    FilesDetected { zh: "检测到 {} 个文件，共 {}：\n{}", en: "{} files detected, {} in total:\n{}" }
+    FileTooLarge { zh: "文件大小 {} 超过 Bot API 的 20MB 下载限制", en: "File size {} exceeds the 20MB download limit of the Bot API" }
+    NoSourceLink { zh: "无法解析来源链接", en: "Cannot resolve the source link" }
+    DownloadExpired { zh: "下载任务已失效", en: "This download has expired" }
+    TaskExpired { zh: "任务已失效", en: "This task has expired" }
+    ButtonExpired { zh: "按钮已失效，请重新打开", en: "This button has expired, please open it again" }
+    RemoteDownloadSubmitted { zh: "远程下载成功", en: "Remote download submitted" }
+    RemoteDownloadFailed { zh: "远程下载失败: {}", en: "Remote download failed: {}" }
+    UploadingTorrent { zh: "正在上传种子文件...", en: "Uploading the torrent file..." }
+    UploadingFile { zh: "正在上传文件...", en: "Uploading the file..." }
+    FileSaved { zh: "✅ 已保存到云盘\n<code>{}</code>", en: "✅ Saved to Cloudreve\n<code>{}</code>" }
+    FilesSaved { zh: "✅ 已将 {} 个文件保存到云盘\n<code>{}</code>", en: "✅ Saved {} files to Cloudreve\n<code>{}</code>" }
+    SaveFileFailed { zh: "保存文件失败: {}", en: "Failed to save file: {}" }
+
+    // 下载状态
+    FetchingDownloadStatus { zh: "正在获取下载状态...", en: "Fetching download status..." }
+    DownloadWaiting { zh: "<code>{}</code>\n等待下载...", en: "<code>{}</code>\nWaiting for download..." }
+    DownloadProgress { zh: "<b>{}</b>\n文件大小: {}\n下载进度: {}%", en: "<b>{}</b>\nSize: {}\nProgress: {}%" }
+    DownloadEnded { zh: "下载已结束", en: "Download ended" }
+    DownloadNotFound { zh: "<code>{}</code>\n未找到下载任务", en: "<code>{}</code>\nDownload task not found" }
+    DownloadsFinished { zh: "下载任务 {}/{} 已完成\n\n{}", en: "{}/{} downloads finished\n\n{}" }
+    DownloadsNotTracked { zh: "远程下载成功，同时跟踪的任务过多，可用 /tasks 查看进度", en: "Remote download submitted. Too many tasks are being tracked, use /tasks to check it" }
+    DownloadsUntracked { zh: "下载仍在进行，已停止跟踪，可用 /tasks 查看进度", en: "Downloads are still running and no longer tracked, use /tasks to check them" }
+    DownloadCompletedLine { zh: "✅ <b>{}</b>\n下载完成", en: "✅ <b>{}</b>\nDownload completed" }
+    DownloadFailedLine { zh: "❌ <b>{}</b>\n下载失败", en: "❌ <b>{}</b>\nDownload failed" }
+    DownloadEndedLine { zh: "<b>{}</b>\n下载已结束", en: "<b>{}</b>\nDownload ended" }
+    DownloadCompleted { zh: "✅ 下载完成\n<b>{}</b>\n保存位置: <code>{}/{}</code>\n文件大小: {}\n耗时: {}", en: "✅ Download completed\n<b>{}</b>\nSaved to: <code>{}/{}</code>\nSize: {}\nTime: {}" }
+    DownloadFailed { zh: "❌ 下载失败\n<b>{}</b>\n原因: {}", en: "❌ Download failed\n<b>{}</b>\nReason: {}" }
+    DurationHours { zh: "{}小时{}分{}秒", en: "{}h {}m {}s" }
+    DurationMinutes { zh: "{}分{}秒", en: "{}m {}s" }
+    DurationSeconds { zh: "{}秒", en: "{}s" }
+
+    // 压缩、解压任务
+    WorkflowArchive { zh: "压缩", en: "Archive" }
+    WorkflowExtract { zh: "解压", en: "Extraction" }
+    WorkflowSubmitted { zh: "⏳ {}任务已提交", en: "⏳ {} task submitted" }
+    WorkflowSubmitFailed { zh: "{}失败: {}", en: "{} failed: {}" }
+    WorkflowRunning { zh: "⏳ {}中 ({})\n已用时: {}", en: "⏳ {} in progress ({})\nElapsed: {}" }
+    WorkflowNotFound { zh: "{}: 未找到任务", en: "{}: task not found" }
+    WorkflowCompleted { zh: "✅ {}完成", en: "✅ {} completed" }
+    WorkflowCompletedNotice { zh: "✅ {}完成\n位置: <code>{}</code>\n耗时: {}", en: "✅ {} completed\nLocation: <code>{}</code>\nTime: {}" }
+    WorkflowFailed { zh: "❌ {}失败", en: "❌ {} failed" }
+    WorkflowFailedNotice { zh: "❌ {}失败\n原因: {}", en: "❌ {} failed\nReason: {}" }
+    WorkflowNotTracked { zh: "⏳ {}任务已提交，同时跟踪的任务过多，不再更新进度", en: "⏳ {} task submitted. Too many tasks are being tracked, progress will not be updated" }
+    WorkflowUntracked { zh: "⏳ {}仍在进行，已停止跟踪", en: "⏳ {} is still running and no longer tracked" }
+}
+
+/// Fills the `{}` placeholders of a template in order.
+pub fn format(template: &str, args: &[String]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut rest = template;
+    while let Some(pos) = rest.find("{}") {
+        out.push_str(&rest[..pos]);
+        if let Some(arg) = args.next() {
+            out.push_str(arg);
+        }
+        rest = &rest[pos + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `tr!(lang, Key)` or `tr!(lang, Key, args...)`: the message in `lang`, as a `String`.
+macro_rules! tr {
+    ($lang:expr, $key:ident) => {
+        $crate::i18n::Msg::$key.text($lang).to_string()
+    };
+    ($lang:expr, $key:ident, $($arg:expr),+ $(,)?) => {
+        $crate::i18n::format(
+            $crate::i18n::Msg::$key.text($lang),
+            &[$($arg.to_string()),+],
+        )
+    };
+}
+pub(crate) use tr;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_code_defaults_to_chinese() {
+        assert_eq!(Lang::from_code(None), Lang::Zh);
+        assert_eq!(Lang::from_code(Some("zh-hans")), Lang::Zh);
+        assert_eq!(Lang::from_code(Some("en")), Lang::En);
+        assert_eq!(Lang::from_code(Some("ru")), Lang::En);
+    }
+
+    #[test]
+    fn parse_accepts_codes_and_names() {
+        assert_eq!(Lang::parse(" EN "), Some(Lang::En));
+        assert_eq!(Lang::parse("english"), Some(Lang::En));
+        assert_eq!(Lang::parse("cn"), Some(Lang::Zh));
+        assert_eq!(Lang::parse("中文"), Some(Lang::Zh));
+        assert_eq!(Lang::parse("fr"), None);
+        for lang in [Lang::Zh, Lang::En] {
+            assert_eq!(Lang::parse(lang.as_str()), Some(lang));
+        }
+    }
+}
@@ -14,6 +14,11 @@ mod cloudreve;
 use cloudreve::CloudreveClient;
 mod bot;
 use bot::Command;
+mod i18n;
+mod resolver;
+use resolver::ResolverRegistry;
+mod state;
+use state::StateStore;
 
 use std::collections::HashMap;
 use tokio::sync::Mutex;
@@ -54,9 +59,12 @@ async fn main() {
     });
 
     let bot_instance = Bot::new(token);
+    bot::register_commands(&bot_instance).await;
     let client = Arc::new(client);
     // Initialize page token cache
     let cache: Arc<Mutex<HashMap<String, String>>> = Arc::new(Mutex::new(HashMap::new()));
+    let state = StateStore::new();
+    let resolvers = Arc::new(ResolverRegistry::from_env().expect("Invalid LINK_RESOLVERS"));
 
     let handler = Update::filter_message()
         .branch(
@@ -67,29 +75,46 @@ async fn main() {
                      msg: Message,
                      cmd: Command,
                      client: Arc<CloudreveClient>,
-                     cache: Arc<Mutex<HashMap<String, String>>>| async move {
-                        bot::answer(bot, msg, cmd, client, cache).await
+                     cache: Arc<Mutex<HashMap<String, String>>>,
+                     state: StateStore| async move {
+                        bot::answer(bot, msg, cmd, client, cache, state).await
                     },
                 ),
         )
-        .branch(dptree::endpoint(|bot: Bot, msg: Message| async move {
-            bot::message_handler(bot, msg).await
-        }));
+        .branch(dptree::endpoint(
+            |bot: Bot,
+             msg: Message,
+             state: StateStore,
+             resolvers: Arc<ResolverRegistry>,
+             client: Arc<CloudreveClient>| async move {
+                bot::message_handler(bot, msg, state, resolvers, client).await
+            },
+        ));
 
     let callback_handler = Update::filter_callback_query().endpoint(
         |bot: Bot,
          q: CallbackQuery,
          client: Arc<CloudreveClient>,
-         cache: Arc<Mutex<HashMap<String, String>>>| async move {
-            bot::callback_handler(bot, q, client, cache).await
+         cache: Arc<Mutex<HashMap<String, String>>>,
+         state: StateStore| async move {
+            bot::callback_handler(bot, q, client, cache, state).await
+        },
+    );
+
+    let inline_query_handler = Update::filter_inline_query().endpoint(
+        |bot: Bot, q: InlineQuery, client: Arc<CloudreveClient>| async move {
+            bot::inline_query_handler(bot, q, client).await
         },
     );
 
     Dispatcher::builder(
         bot_instance,
-        dptree::entry().branch(handler).branch(callback_handler),
+        dptree::entry()
+            .branch(handler)
+            .branch(callback_handler)
+            .branch(inline_query_handler),
     )
-    .dependencies(dptree::deps![client, cache])
+    .dependencies(dptree::deps![client, cache, state, resolvers])
     .enable_ctrlc_handler()
     .build()
     .dispatch()
@@ -0,0 +1,265 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::info;
+use regex::Regex;
+use serde_json::Value;
+use std::env;
+use std::time::Duration;
+
+const DEFAULT_RESOLVER_URL: &str = "https://tg.taro.cat";
+const DEFAULT_HOST_PATTERN: &str = r"^(www\.)?t(elegram)?\.me$";
+const DEFAULT_TIMEOUT_SECS: u64 = 15;
+
+/// Turns a share link (e.g. a `t.me` post) into a direct stream link.
+#[async_trait]
+pub trait LinkResolver: Send + Sync {
+    /// Name used in logs and error messages.
+    fn name(&self) -> &str;
+
+    async fn resolve(&self, url: &str) -> Result<String>;
+}
+
+/// Resolver backed by a tg-resolve style service exposing `GET /api/resolve?url=`.
+pub struct ResolveService {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl ResolveService {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl LinkResolver for ResolveService {
+    fn name(&self) -> &str {
+        &self.base_url
+    }
+
+    async fn resolve(&self, url: &str) -> Result<String> {
+        let resp = self
+            .client
+            .get(format!("{}/api/resolve", self.base_url))
+            .query(&[("url", url)])
+            .send()
+            .await?;
+        let text = resp.text().await?;
+        let api_resp: Value = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("Failed to parse response: {} - {}", e, text))?;
+        if !api_resp
+            .get("ok")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+        {
+            return Err(anyhow!("Failed to resolve URL"));
+        }
+        if let Some(download_url) = api_resp.get("stream_link").and_then(|v| v.as_str()) {
+            Ok(download_url.to_string())
+        } else {
+            Err(anyhow!("Failed to resolve URL"))
+        }
+    }
+}
+
+struct ResolverEntry {
+    host_pattern: Regex,
+    resolver: Box<dyn LinkResolver>,
+    timeout: Duration,
+}
+
+/// Ordered list of resolvers, each applied to links whose host matches its pattern.
+#[derive(Default)]
+pub struct ResolverRegistry {
+    entries: Vec<ResolverEntry>,
+}
+
+impl ResolverRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the registry from `LINK_RESOLVERS` (see `from_config`). Without it a
+    /// single resolver for `t.me` links is registered, using `LINK_RESOLVER_URL`.
+    pub fn from_env() -> Result<Self> {
+        match env::var("LINK_RESOLVERS") {
+            Ok(config) => Self::from_config(&config),
+            Err(_) => {
+                let mut registry = Self::new();
+                let base_url = env::var("LINK_RESOLVER_URL")
+                    .unwrap_or_else(|_| DEFAULT_RESOLVER_URL.to_string());
+                registry.register(
+                    DEFAULT_HOST_PATTERN,
+                    ResolveService::new(&base_url),
+                    Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+                )?;
+                Ok(registry)
+            }
+        }
+    }
+
+    /// Builds the registry from a `;`-separated list of
+    /// `host_pattern=base_url[=timeout_secs]` entries.
+    pub fn from_config(config: &str) -> Result<Self> {
+        let mut registry = Self::new();
+        for entry in config
+            .split(';')
+            .map(|e| e.trim())
+            .filter(|e| !e.is_empty())
+        {
+            let (pattern, base_url, timeout) = parse_entry(entry)?;
+            registry.register(
+                pattern,
+                ResolveService::new(base_url),
+                Duration::from_secs(timeout),
+            )?;
+        }
+        Ok(registry)
+    }
+
+    pub fn register(
+        &mut self,
+        host_pattern: &str,
+        resolver: impl LinkResolver + 'static,
+        timeout: Duration,
+    ) -> Result<()> {
+        let host_pattern = Regex::new(host_pattern)
+            .map_err(|e| anyhow!("Invalid host pattern {}: {}", host_pattern, e))?;
+        self.entries.push(ResolverEntry {
+            host_pattern,
+            resolver: Box::new(resolver),
+            timeout,
+        });
+        Ok(())
+    }
+
+    /// Returns true if at least one resolver handles this link.
+    pub fn matches(&self, url: &str) -> bool {
+        self.matching(url).next().is_some()
+    }
+
+    /// Tries every matching resolver in registration order and returns the first success.
+    pub async fn resolve(&self, url: &str) -> Result<String> {
+        let mut errors = Vec::new();
+        for entry in self.matching(url) {
+            let name = entry.resolver.name();
+            match tokio::time::timeout(entry.timeout, entry.resolver.resolve(url)).await {
+                Ok(Ok(link)) => return Ok(link),
+                Ok(Err(e)) => errors.push(format!("{}: {}", name, e)),
+                Err(_) => errors.push(format!("{}: timed out", name)),
+            }
+            info!("Resolver {} failed for {}", name, url);
+        }
+        if errors.is_empty() {
+            Err(anyhow!("No resolver for {}", url))
+        } else {
+            Err(anyhow!(errors.join("; ")))
+        }
+    }
+
+    fn matching<'a>(&'a self, url: &str) -> impl Iterator<Item = &'a ResolverEntry> {
+        let host = link_host(url);
+        self.entries.iter().filter(move |entry| {
+            host.as_deref()
+                .map(|host| entry.host_pattern.is_match(host))
+                .unwrap_or(false)
+        })
+    }
+}
+
+// host_pattern=base_url[=timeout_secs]；base_url 的查询参数中可能有 '='，
+// 所以只在最后一段全是数字时才当作超时时间
+fn parse_entry(entry: &str) -> Result<(&str, &str, u64)> {
+    let (pattern, rest) = entry
+        .split_once('=')
+        .filter(|(_, rest)| !rest.is_empty())
+        .ok_or_else(|| anyhow!("Invalid resolver entry: {}", entry))?;
+    match rest.rsplit_once('=') {
+        Some((base_url, secs)) if !secs.is_empty() && secs.chars().all(|c| c.is_ascii_digit()) => {
+            let timeout = secs
+                .parse::<u64>()
+                .map_err(|e| anyhow!("Invalid resolver timeout {}: {}", secs, e))?;
+            Ok((pattern, base_url, timeout))
+        }
+        _ => Ok((pattern, rest, DEFAULT_TIMEOUT_SECS)),
+    }
+}
+
+// Url 实体可能不带协议，例如 "t.me/channel/1"
+fn link_host(url: &str) -> Option<String> {
+    let parsed = reqwest::Url::parse(url)
+        .or_else(|_| reqwest::Url::parse(&format!("https://{}", url)))
+        .ok()?;
+    parsed.host_str().map(|host| host.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_entry_keeps_equals_signs_in_the_base_url() {
+        assert_eq!(
+            parse_entry(r"^t\.me$=https://tg.taro.cat=15").unwrap(),
+            (r"^t\.me$", "https://tg.taro.cat", 15)
+        );
+        assert_eq!(
+            parse_entry("^t.me$=https://example.com/?key=abc").unwrap(),
+            (
+                "^t.me$",
+                "https://example.com/?key=abc",
+                DEFAULT_TIMEOUT_SECS
+            )
+        );
+        assert_eq!(
+            parse_entry("^t.me$=https://example.com/?key=abc=30").unwrap(),
+            ("^t.me$", "https://example.com/?key=abc", 30)
+        );
+        assert!(parse_entry("^t.me$").is_err());
+        assert!(parse_entry("^t.me$=").is_err());
+    }
+
+    #[test]
+    fn from_config_registers_entries_in_order() {
+        let registry = ResolverRegistry::from_config(
+            r"^t\.me$=https://a.example.com=15; ;^x\.com$=https://b.example.com",
+        )
+        .unwrap();
+        assert_eq!(registry.entries.len(), 2);
+        assert_eq!(registry.entries[0].resolver.name(), "https://a.example.com");
+        assert_eq!(
+            registry.entries[1].timeout,
+            Duration::from_secs(DEFAULT_TIMEOUT_SECS)
+        );
+        assert!(ResolverRegistry::from_config("(=https://a.example.com").is_err());
+    }
+
+    #[test]
+    fn link_host_accepts_links_without_scheme() {
+        assert_eq!(link_host("t.me/channel/1").as_deref(), Some("t.me"));
+        assert_eq!(
+            link_host("https://Telegram.ME/channel/1").as_deref(),
+            Some("telegram.me")
+        );
+        assert_eq!(link_host(""), None);
+    }
+
+    #[test]
+    fn matches_uses_the_host_pattern() {
+        let mut registry = ResolverRegistry::new();
+        registry
+            .register(
+                DEFAULT_HOST_PATTERN,
+                ResolveService::new("https://tg.taro.cat"),
+                Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            )
+            .unwrap();
+        assert!(registry.matches("https://t.me/channel/1"));
+        assert!(registry.matches("www.telegram.me/channel/1"));
+        assert!(!registry.matches("https://example.com/t.me/1"));
+        assert!(!registry.matches("https://nott.me/channel/1"));
+    }
+}
@@ -0,0 +1,607 @@
+use crate::cloudreve::{FileFilter, OrderBy, OrderDirection};
+use crate::i18n::Lang;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use teloxide::types::{ChatId, MessageId, User, UserId};
+use tokio::sync::{Mutex, MutexGuard};
+
+const MAX_RECENT_DESTINATIONS: usize = 5;
+// 目录统计结果的缓存时间
+const FOLDER_STATS_TTL: Duration = Duration::from_secs(10 * 60);
+// 列表标题中目录合计的缓存时间，翻页时不必重新统计
+const FOLDER_TOTALS_TTL: Duration = Duration::from_secs(60);
+// 待确认的操作、等待回复的提示和选择模式的保留时间，过期后按钮失效
+const PENDING_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+// 按钮引用的路径数上限，超出时丢弃最早分配的
+const MAX_PATH_IDS: usize = 10_000;
+
+// Callback data is limited to 64 bytes, so anything longer than a short id
+// (resolved stream links, magnet URIs, Telegram file ids) is kept here and
+// referenced from the inline button by id.
+
+/// A source that can be submitted to Cloudreve's remote download workflow.
+#[derive(Clone, Debug)]
+pub enum DownloadSource {
+    /// A direct URL or magnet URI, passed to Cloudreve as-is.
+    Url(String),
+    /// Several URLs submitted together as one download request.
+    Batch(Vec<String>),
+    /// A `.torrent` document sent to the bot, uploaded to Cloudreve before submitting.
+    Torrent { file_id: String, file_name: String },
+    /// Files attached to a forwarded message or album, uploaded to Cloudreve directly.
+    TelegramFiles(Vec<TelegramFile>),
+}
+
+/// A file attached to a message, small enough to download through the Bot API.
+#[derive(Clone, Debug)]
+pub struct TelegramFile {
+    pub file_id: String,
+    pub file_name: String,
+    pub size: u32,
+}
+
+/// Links and files collected from the messages of one media group.
+#[derive(Default)]
+pub struct MediaGroup {
+    pub links: Vec<String>,
+    pub files: Vec<TelegramFile>,
+}
+
+/// What to do with the destination folder chosen in the picker.
+#[derive(Clone, Debug)]
+pub enum PendingAction {
+    Download(DownloadSource),
+    /// Move, or copy, existing files into the chosen folder.
+    Move {
+        uris: Vec<String>,
+        copy: bool,
+    },
+    /// Use the chosen folder as the chat's browse root.
+    SetRoot,
+    /// Use the chosen folder as the chat's default download destination.
+    SetDownloadDestination,
+}
+
+/// An action waiting for the user to confirm it, optionally with a chosen destination.
+#[derive(Clone, Debug)]
+pub struct Pending {
+    pub action: PendingAction,
+    /// Destination folder uri; `None` means the default download folder.
+    pub dst: Option<String>,
+    /// Folder currently shown in the destination picker.
+    pub browse_path: Option<String>,
+}
+
+/// Sort order and filter of the file browser, chosen per chat.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListOptions {
+    pub order_by: OrderBy,
+    pub order_direction: OrderDirection,
+    pub filter: FileFilter,
+}
+
+/// Per-chat settings changed from the `/settings` menu.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatSettings {
+    /// Entries per page of the file browser.
+    pub page_size: u32,
+    /// Folder opened by `/list` without a path; `None` means `CLOUDEREVE_BASE_PATH`.
+    pub root: Option<String>,
+    /// Default download destination; `None` means `CLOUDEREVE_DOWNLOAD_PATH`.
+    pub download_dst: Option<String>,
+    /// Whether completion notifications play a sound.
+    pub notify: bool,
+    /// Whether plain http(s) links are offered as remote downloads.
+    pub auto_offer: bool,
+}
+
+impl Default for ChatSettings {
+    fn default() -> Self {
+        Self {
+            page_size: 10,
+            root: None,
+            download_dst: None,
+            notify: true,
+            auto_offer: true,
+        }
+    }
+}
+
+/// Files picked in a browser message switched to selection mode.
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    /// Folder being browsed.
+    pub path: String,
+    /// Entries shown on the current page, toggled by index from the buttons.
+    pub entries: Vec<String>,
+    /// Selected uris, in the order they were picked.
+    pub selected: Vec<String>,
+}
+
+/// Totals of a folder tree, computed by walking it.
+#[derive(Clone, Debug, Default)]
+pub struct FolderStats {
+    pub files: u64,
+    pub folders: u64,
+    pub total_size: u64,
+    /// File count and size per type icon, e.g. "🎬".
+    pub by_type: HashMap<&'static str, (u64, u64)>,
+    /// Folders that could not be listed, with the error.
+    pub failed: Vec<String>,
+}
+
+/// Free-text input expected as a reply to a prompt sent by the bot.
+#[derive(Clone, Debug)]
+pub enum PendingInput {
+    Rename { uri: String },
+}
+
+/// Settings that survive restarts, saved as JSON to `PREFERENCES_FILE`.
+#[derive(Default, Serialize, Deserialize)]
+struct Preferences {
+    /// Language chosen with `/lang`, by user id.
+    #[serde(default)]
+    languages: HashMap<u64, Lang>,
+    /// Browser sort order and filter, by chat id.
+    #[serde(default)]
+    list_options: HashMap<i64, ListOptions>,
+    #[serde(default)]
+    chat_settings: HashMap<i64, ChatSettings>,
+    /// Folder changed into with `/cd`, by chat id.
+    #[serde(default)]
+    cwd: HashMap<i64, String>,
+    /// Most recently used download destinations, newest first, by user id.
+    #[serde(default)]
+    recent_destinations: HashMap<u64, Vec<String>>,
+}
+
+fn preferences_file() -> String {
+    env::var("PREFERENCES_FILE").unwrap_or_else(|_| "preferences.json".to_string())
+}
+
+impl Preferences {
+    // 文件不存在时使用默认设置
+    fn load() -> Self {
+        let path = preferences_file();
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                error!("Invalid preferences file {}: {}", path, e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    // 先写临时文件再重命名，写到一半时退出也不会损坏原文件
+    fn write(path: &str, content: &str) -> std::io::Result<()> {
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, content)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    pending: HashMap<u64, (Instant, Pending)>,
+    pending_inputs: HashMap<(ChatId, MessageId), (Instant, PendingInput)>,
+    media_groups: HashMap<String, MediaGroup>,
+    selections: HashMap<(ChatId, MessageId), (Instant, Selection)>,
+    folder_stats: HashMap<String, (Instant, FolderStats)>,
+    folder_totals: HashMap<String, (Instant, FolderStats)>,
+    message_versions: HashMap<(ChatId, MessageId), (Instant, u64)>,
+    preferences: Preferences,
+    // 每次修改设置加一，用于跳过较旧的保存
+    preferences_version: u64,
+}
+
+/// Paths referenced from inline buttons, indexed both ways so that a path
+/// shown again gets the id it already has.
+#[derive(Default)]
+struct PathIds {
+    next_id: u64,
+    by_id: BTreeMap<u64, String>,
+    by_path: HashMap<String, u64>,
+}
+
+#[derive(Clone, Default)]
+pub struct StateStore {
+    inner: Arc<Mutex<State>>,
+    // 构建键盘的函数大多是同步的，路径表单独使用同步锁，持有期间不会 await
+    paths: Arc<std::sync::Mutex<PathIds>>,
+    // 已写入文件的设置版本，写文件期间持有
+    saved_version: Arc<std::sync::Mutex<u64>>,
+}
+
+impl StateStore {
+    /// A store with the preferences saved by a previous run.
+    pub fn new() -> Self {
+        let state = State {
+            preferences: Preferences::load(),
+            ..Default::default()
+        };
+        Self {
+            inner: Arc::new(Mutex::new(state)),
+            paths: Default::default(),
+            saved_version: Default::default(),
+        }
+    }
+
+    /// Saves the preferences: serialized while `state` is locked, written to disk
+    /// after releasing it. A save that lost the race to a newer one is skipped.
+    async fn save_preferences(&self, mut state: MutexGuard<'_, State>) {
+        state.preferences_version += 1;
+        let version = state.preferences_version;
+        let content = serde_json::to_string_pretty(&state.preferences);
+        drop(state);
+        let saved_version = self.saved_version.clone();
+        let path = preferences_file();
+        let saved = tokio::task::spawn_blocking(move || {
+            let mut saved_version = saved_version.lock().unwrap();
+            if *saved_version > version {
+                return Ok(());
+            }
+            let content = content.map_err(|e| e.to_string())?;
+            Preferences::write(&path, &content).map_err(|e| e.to_string())?;
+            *saved_version = version;
+            Ok::<_, String>(())
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|saved| saved);
+        if let Err(e) = saved {
+            error!(
+                "Failed to save preferences to {}: {}",
+                preferences_file(),
+                e
+            );
+        }
+    }
+
+    /// Short id of a file or folder uri, for callback data.
+    pub fn path_id(&self, path: &str) -> u64 {
+        let mut paths = self.paths.lock().unwrap();
+        if let Some(id) = paths.by_path.get(path) {
+            return *id;
+        }
+        paths.next_id += 1;
+        let id = paths.next_id;
+        paths.by_id.insert(id, path.to_string());
+        paths.by_path.insert(path.to_string(), id);
+        if paths.by_id.len() > MAX_PATH_IDS {
+            if let Some((_, oldest)) = paths.by_id.pop_first() {
+                paths.by_path.remove(&oldest);
+            }
+        }
+        id
+    }
+
+    /// The uri behind an id from `path_id`; `None` once it was dropped or after a restart.
+    pub fn path(&self, id: &str) -> Option<String> {
+        let id = id.parse::<u64>().ok()?;
+        self.paths.lock().unwrap().by_id.get(&id).cloned()
+    }
+
+    pub async fn add_pending(&self, action: PendingAction) -> u64 {
+        let mut state = self.inner.lock().await;
+        state
+            .pending
+            .retain(|_, (created, _)| created.elapsed() < PENDING_TTL);
+        state.next_id += 1;
+        let id = state.next_id;
+        state.pending.insert(
+            id,
+            (
+                Instant::now(),
+                Pending {
+                    action,
+                    dst: None,
+                    browse_path: None,
+                },
+            ),
+        );
+        id
+    }
+
+    pub async fn add_pending_download(&self, source: DownloadSource) -> u64 {
+        self.add_pending(PendingAction::Download(source)).await
+    }
+
+    pub async fn pending(&self, id: u64) -> Option<Pending> {
+        let state = self.inner.lock().await;
+        state.pending.get(&id).map(|(_, pending)| pending.clone())
+    }
+
+    /// Removes a pending action for submitting it, so a second click finds nothing.
+    pub async fn take_pending(&self, id: u64) -> Option<Pending> {
+        let mut state = self.inner.lock().await;
+        state.pending.remove(&id).map(|(_, pending)| pending)
+    }
+
+    pub async fn set_pending_browse_path(&self, id: u64, path: &str) {
+        let mut state = self.inner.lock().await;
+        if let Some((_, pending)) = state.pending.get_mut(&id) {
+            pending.browse_path = Some(path.to_string());
+        }
+    }
+
+    pub async fn set_pending_dst(&self, id: u64, dst: &str) {
+        let mut state = self.inner.lock().await;
+        if let Some((_, pending)) = state.pending.get_mut(&id) {
+            pending.dst = Some(dst.to_string());
+        }
+    }
+
+    /// Most recently used download destinations of a user, newest first.
+    pub async fn recent_destinations(&self, user: UserId) -> Vec<String> {
+        let state = self.inner.lock().await;
+        state
+            .preferences
+            .recent_destinations
+            .get(&user.0)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn add_recent_destination(&self, user: UserId, dst: &str) {
+        let mut state = self.inner.lock().await;
+        let recent = state
+            .preferences
+            .recent_destinations
+            .entry(user.0)
+            .or_default();
+        if recent.first().is_some_and(|d| d == dst) {
+            return;
+        }
+        recent.retain(|d| d != dst);
+        recent.insert(0, dst.to_string());
+        recent.truncate(MAX_RECENT_DESTINATIONS);
+        self.save_preferences(state).await;
+    }
+
+    /// Buffers the links and files of one message of a media group. Returns true
+    /// for the first message of the group, whose handler is responsible for the batch.
+    pub async fn collect_media_group(
+        &self,
+        group_id: &str,
+        links: Vec<String>,
+        files: Vec<TelegramFile>,
+    ) -> bool {
+        let mut state = self.inner.lock().await;
+        let first = !state.media_groups.contains_key(group_id);
+        let group = state.media_groups.entry(group_id.to_string()).or_default();
+        group.links.extend(links);
+        group.files.extend(files);
+        first
+    }
+
+    pub async fn take_media_group(&self, group_id: &str) -> MediaGroup {
+        let mut state = self.inner.lock().await;
+        state.media_groups.remove(group_id).unwrap_or_default()
+    }
+
+    /// Remembers that a reply to the prompt `message_id` is input for `input`.
+    pub async fn add_pending_input(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        input: PendingInput,
+    ) {
+        let mut state = self.inner.lock().await;
+        state
+            .pending_inputs
+            .retain(|_, (created, _)| created.elapsed() < PENDING_TTL);
+        state
+            .pending_inputs
+            .insert((chat_id, message_id), (Instant::now(), input));
+    }
+
+    pub async fn take_pending_input(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> Option<PendingInput> {
+        let mut state = self.inner.lock().await;
+        state
+            .pending_inputs
+            .remove(&(chat_id, message_id))
+            .map(|(_, input)| input)
+    }
+
+    pub async fn list_options(&self, chat_id: ChatId) -> ListOptions {
+        let state = self.inner.lock().await;
+        state
+            .preferences
+            .list_options
+            .get(&chat_id.0)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub async fn set_list_options(&self, chat_id: ChatId, options: ListOptions) {
+        let mut state = self.inner.lock().await;
+        state.preferences.list_options.insert(chat_id.0, options);
+        self.save_preferences(state).await;
+    }
+
+    pub async fn chat_settings(&self, chat_id: ChatId) -> ChatSettings {
+        let state = self.inner.lock().await;
+        state
+            .preferences
+            .chat_settings
+            .get(&chat_id.0)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn set_chat_settings(&self, chat_id: ChatId, settings: ChatSettings) {
+        let mut state = self.inner.lock().await;
+        state.preferences.chat_settings.insert(chat_id.0, settings);
+        self.save_preferences(state).await;
+    }
+
+    /// The chat's current folder, or `None` before the first `/cd`.
+    pub async fn cwd(&self, chat_id: ChatId) -> Option<String> {
+        let state = self.inner.lock().await;
+        state.preferences.cwd.get(&chat_id.0).cloned()
+    }
+
+    pub async fn set_cwd(&self, chat_id: ChatId, path: &str) {
+        let mut state = self.inner.lock().await;
+        state.preferences.cwd.insert(chat_id.0, path.to_string());
+        self.save_preferences(state).await;
+    }
+
+    /// Switches a browser message to selection mode, starting with nothing selected.
+    pub async fn start_selection(&self, chat_id: ChatId, message_id: MessageId, path: &str) {
+        let mut state = self.inner.lock().await;
+        state
+            .selections
+            .retain(|_, (touched, _)| touched.elapsed() < PENDING_TTL);
+        state.selections.insert(
+            (chat_id, message_id),
+            (
+                Instant::now(),
+                Selection {
+                    path: path.to_string(),
+                    ..Default::default()
+                },
+            ),
+        );
+    }
+
+    pub async fn selection(&self, chat_id: ChatId, message_id: MessageId) -> Option<Selection> {
+        let state = self.inner.lock().await;
+        state
+            .selections
+            .get(&(chat_id, message_id))
+            .map(|(_, selection)| selection.clone())
+    }
+
+    pub async fn set_selection_entries(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        entries: Vec<String>,
+    ) {
+        let mut state = self.inner.lock().await;
+        if let Some((touched, selection)) = state.selections.get_mut(&(chat_id, message_id)) {
+            *touched = Instant::now();
+            selection.entries = entries;
+        }
+    }
+
+    /// Selects or unselects the entry at `index` of the current page.
+    pub async fn toggle_selection(&self, chat_id: ChatId, message_id: MessageId, index: usize) {
+        let mut state = self.inner.lock().await;
+        if let Some((touched, selection)) = state.selections.get_mut(&(chat_id, message_id)) {
+            *touched = Instant::now();
+            if let Some(entry) = selection.entries.get(index).cloned() {
+                if let Some(pos) = selection.selected.iter().position(|s| *s == entry) {
+                    selection.selected.remove(pos);
+                } else {
+                    selection.selected.push(entry);
+                }
+            }
+        }
+    }
+
+    pub async fn take_selection(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> Option<Selection> {
+        let mut state = self.inner.lock().await;
+        state
+            .selections
+            .remove(&(chat_id, message_id))
+            .map(|(_, selection)| selection)
+    }
+
+    /// Cached stats of a folder, if computed within the last few minutes.
+    pub async fn folder_stats(&self, path: &str) -> Option<FolderStats> {
+        let state = self.inner.lock().await;
+        state
+            .folder_stats
+            .get(path)
+            .filter(|(computed, _)| computed.elapsed() < FOLDER_STATS_TTL)
+            .map(|(_, stats)| stats.clone())
+    }
+
+    pub async fn set_folder_stats(&self, path: &str, stats: FolderStats) {
+        let mut state = self.inner.lock().await;
+        state
+            .folder_stats
+            .retain(|_, (computed, _)| computed.elapsed() < FOLDER_STATS_TTL);
+        state
+            .folder_stats
+            .insert(path.to_string(), (Instant::now(), stats));
+    }
+
+    /// Cached totals of the direct children of a folder, for listing headers.
+    pub async fn folder_totals(&self, path: &str) -> Option<FolderStats> {
+        let state = self.inner.lock().await;
+        state
+            .folder_totals
+            .get(path)
+            .filter(|(computed, _)| computed.elapsed() < FOLDER_TOTALS_TTL)
+            .map(|(_, totals)| totals.clone())
+    }
+
+    pub async fn set_folder_totals(&self, path: &str, totals: FolderStats) {
+        let mut state = self.inner.lock().await;
+        state
+            .folder_totals
+            .retain(|_, (computed, _)| computed.elapsed() < FOLDER_TOTALS_TTL);
+        state
+            .folder_totals
+            .insert(path.to_string(), (Instant::now(), totals));
+    }
+
+    /// Marks a message as changed, so that background edits prepared for an
+    /// earlier version of it are dropped.
+    pub async fn bump_message_version(&self, chat_id: ChatId, message_id: MessageId) {
+        let mut state = self.inner.lock().await;
+        state.next_id += 1;
+        let version = state.next_id;
+        state
+            .message_versions
+            .retain(|_, (changed, _)| changed.elapsed() < PENDING_TTL);
+        state
+            .message_versions
+            .insert((chat_id, message_id), (Instant::now(), version));
+    }
+
+    pub async fn message_version(&self, chat_id: ChatId, message_id: MessageId) -> u64 {
+        let state = self.inner.lock().await;
+        state
+            .message_versions
+            .get(&(chat_id, message_id))
+            .map_or(0, |(_, version)| *version)
+    }
+
+    /// The user's `/lang` choice, or the language of their Telegram client.
+    pub async fn lang(&self, user: Option<&User>) -> Lang {
+        let Some(user) = user else {
+            return Lang::default();
+        };
+        let state = self.inner.lock().await;
+        state
+            .preferences
+            .languages
+            .get(&user.id.0)
+            .copied()
+            .unwrap_or_else(|| Lang::from_code(user.language_code.as_deref()))
+    }
+
+    pub async fn set_lang(&self, user: UserId, lang: Lang) {
+        let mut state = self.inner.lock().await;
+        state.preferences.languages.insert(user.0, lang);
+        self.save_preferences(state).await;
+    }
+}